@@ -0,0 +1,274 @@
+extern crate clap;
+extern crate env_logger;
+extern crate ghostscript;
+#[macro_use]
+extern crate log;
+extern crate rustyline;
+
+use ghostscript as gs;
+
+mod session;
+mod syntax;
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use session::Session;
+use std::path::{Path, PathBuf};
+
+const PROMPT: &str = "GS> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".ghostscript_repl_history";
+const DEFAULT_INIT_PARAMS: &[&str] = &["-dNODISPLAY", "-dNOPAUSE", "-dQUIET"];
+
+const HELP: &str = "\
+PostScript entered at the prompt is run in a persistent interpreter session.
+Input with an unclosed procedure or string continues on the next line.
+
+Commands:
+  :load FILE   run a PostScript file in the current session
+  :stack       print the operand stack
+  :reset       discard the session and start a fresh interpreter
+  :help        show this message
+  :quit        leave (Ctrl-D works too)
+Ctrl-C discards the input collected so far.";
+
+enum Flow {
+    Continue,
+    Exit,
+}
+
+struct Repl {
+    init_params: Vec<String>,
+    show_stack: bool,
+    session: Option<Session>,
+}
+
+impl Repl {
+    fn session(&mut self) -> Option<&mut Session> {
+        if self.session.is_none() {
+            match Session::start(&self.init_params) {
+                Ok(session) => self.session = Some(session),
+                Err(e) => eprintln!("Failed to start ghostscript: {}", e),
+            }
+        }
+        self.session.as_mut()
+    }
+
+    fn reset(&mut self) {
+        // The library allows only one live instance at a time, so the old one has to go first.
+        self.session = None;
+        if self.session().is_some() {
+            println!("Session reset.");
+        }
+    }
+
+    fn execute(&mut self, source: &str) -> Flow {
+        let result = match self.session() {
+            Some(session) => session.run(source),
+            None => return Flow::Continue,
+        };
+
+        match result {
+            Ok(()) => {},
+            Err(ref e) if e.error.is_quit() => {
+                println!("Interpreter quit.");
+                return Flow::Exit;
+            },
+            // With the default errordict, ghostscript has already reported the details itself.
+            Err(e) => eprintln!("{}", e),
+        }
+
+        if self.show_stack {
+            self.print_stack();
+        }
+        Flow::Continue
+    }
+
+    fn load(&mut self, file_name: &Path) {
+        let result = match self.session() {
+            Some(session) => session.load(file_name),
+            None => return,
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to load {}: {}", file_name.display(), e);
+        }
+    }
+
+    fn print_stack(&mut self) {
+        let stack = match self.session() {
+            Some(session) => session.stack(),
+            None => return,
+        };
+        match stack {
+            Ok(ref stack) if stack.is_empty() => println!("<empty stack>"),
+            Ok(stack) => {
+                let width = (stack.len() - 1).to_string().len();
+                for (i, element) in stack.iter().enumerate() {
+                    println!("{:>width$}: {}", i, element, width = width);
+                }
+            },
+            Err(e) => eprintln!("Failed to print the operand stack: {}", e),
+        }
+    }
+
+    fn command(&mut self, line: &str) -> Flow {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let command = parts.next().unwrap_or("");
+        let argument = parts.next().map(str::trim).unwrap_or("");
+
+        match (command, argument) {
+            (":quit", "") | (":q", "") => return Flow::Exit,
+            (":help", "") | (":h", "") => println!("{}", HELP),
+            (":stack", "") => self.print_stack(),
+            (":reset", "") => self.reset(),
+            (":load", "") => eprintln!("Usage: :load FILE"),
+            (":load", file_name) => self.load(Path::new(file_name)),
+            _ => eprintln!("Unknown command {:?}, see :help", line),
+        }
+        Flow::Continue
+    }
+}
+
+fn read_init_params(config: &Path) -> Result<Vec<String>, String> {
+    use std::io::Read;
+
+    let mut contents = String::new();
+    ::std::fs::File::open(config)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("Failed to read {}: {}", config.display(), e))?;
+
+    // One init param per line, same as the service configs. Blank lines and # comments are skipped.
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect())
+}
+
+fn default_history_file() -> Option<PathBuf> {
+    ::std::env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+}
+
+fn main() {
+    env_logger::init();
+
+    let matches = clap::App::new("ghostscript-repl")
+        .about("Interactive PostScript prompt on top of the ghostscript crate")
+        .arg(
+            clap::Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Reads interpreter init params from a file, one per line")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("param")
+                .short("p")
+                .long("param")
+                .value_name("PARAM")
+                .help("Adds an interpreter init param, e.g. --param=-dSAFER")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("history")
+                .long("history")
+                .value_name("FILE")
+                .help("Where to keep the line history, defaults to ~/.ghostscript_repl_history")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("no-stack")
+                .long("no-stack")
+                .help("Doesn't print the operand stack after every input"),
+        )
+        .get_matches();
+
+    let mut init_params = match matches.value_of("config") {
+        Some(config) => read_init_params(Path::new(config)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            ::std::process::exit(2)
+        }),
+        None => Vec::new(),
+    };
+    if let Some(params) = matches.values_of("param") {
+        init_params.extend(params.map(|p| p.to_owned()));
+    }
+    if init_params.is_empty() {
+        init_params = DEFAULT_INIT_PARAMS.iter().map(|p| p.to_string()).collect();
+    }
+    debug!("Init params: {:?}", init_params);
+
+    let history_file = matches
+        .value_of("history")
+        .map(PathBuf::from)
+        .or_else(default_history_file);
+
+    let mut repl = Repl {
+        init_params,
+        show_stack: !matches.is_present("no-stack"),
+        session: None,
+    };
+    if repl.session().is_none() {
+        ::std::process::exit(1);
+    }
+
+    let mut editor = Editor::<()>::new();
+    if let Some(ref history_file) = history_file {
+        if let Err(e) = editor.load_history(history_file) {
+            debug!("No history loaded from {}: {}", history_file.display(), e);
+        }
+    }
+
+    let mut pending = String::new();
+    loop {
+        let prompt = if pending.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                pending.clear();
+                continue;
+            },
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                break;
+            },
+        };
+
+        if pending.is_empty() && line.trim_start().starts_with(':') {
+            editor.add_history_entry(line.trim());
+            match repl.command(line.trim()) {
+                Flow::Continue => continue,
+                Flow::Exit => break,
+            }
+        }
+
+        if !pending.is_empty() {
+            pending.push('\n');
+        }
+        pending.push_str(&line);
+        if syntax::is_incomplete(&pending) {
+            continue;
+        }
+
+        let source = ::std::mem::replace(&mut pending, String::new());
+        if source.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(source.as_str());
+        if let Flow::Exit = repl.execute(&source) {
+            break;
+        }
+    }
+
+    if let Some(ref history_file) = history_file {
+        if let Err(e) = editor.save_history(history_file) {
+            warn!("Failed to save history to {}: {}", history_file.display(), e);
+        }
+    }
+}
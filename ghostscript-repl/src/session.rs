@@ -0,0 +1,99 @@
+use gs::builder::{BuilderResult, GhostscriptBuilder};
+use gs::callback::panic::PanicCallback;
+use gs::callback::stdio::StdioCallback;
+use gs::instance::Ghostscript;
+use gs::interpreter::{Interpreter, InterpreterError};
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+// Prints every element of the operand stack with ==, topmost first, leaving the stack intact.
+// The loop counter is pushed before index runs, so 'count 3 sub' is the index of the bottom element.
+const STACK_DUMP: &[u8] = b"0 1 count 3 sub { index == } for flush\n";
+
+// Interpreter output goes straight to the terminal, unless the session is collecting it.
+#[derive(Debug, Default)]
+struct Output {
+    capture: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct ReplStdio {
+    output: Rc<RefCell<Output>>,
+}
+
+impl PanicCallback for ReplStdio {}
+
+impl StdioCallback for ReplStdio {
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        let mut output = self.output.borrow_mut();
+        match output.capture {
+            Some(ref mut captured) => captured.extend_from_slice(buf),
+            None => {
+                let stdout = ::std::io::stdout();
+                let mut stdout = stdout.lock();
+                let _ = stdout.write_all(buf).and_then(|_| stdout.flush());
+            },
+        }
+        buf.len()
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        let _ = ::std::io::stderr().write_all(buf);
+        buf.len()
+    }
+}
+
+#[derive(Debug)]
+pub struct Session {
+    instance: Ghostscript<Box<ReplStdio>>,
+    output: Rc<RefCell<Output>>,
+}
+
+impl Session {
+    pub fn start(init_params: &[String]) -> Result<Session, String> {
+        let stdio = Box::new(ReplStdio::default());
+        let output = stdio.output.clone();
+
+        let mut builder = GhostscriptBuilder::new();
+        builder
+            .with_init_params(init_params)
+            .with_stdout(true)
+            .with_stderr(true);
+
+        match builder.build(stdio) {
+            BuilderResult::Running(instance) => Ok(Session { instance, output }),
+            BuilderResult::Quit(_) => Err("interpreter quit during initialization, check the init params".into()),
+            BuilderResult::Failed(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), InterpreterError> {
+        self.instance.interpret_buffer(source.as_bytes()).into_result().map(|_| ())
+    }
+
+    pub fn load(&mut self, file_name: &Path) -> Result<(), String> {
+        let file_name = file_name
+            .to_str()
+            .ok_or_else(|| format!("File name is not valid UTF-8: {}", file_name.display()))?;
+        self.instance
+            .interpret_file(file_name)
+            .into_result()
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    // Operand stack as printed by ==, topmost element first.
+    pub fn stack(&mut self) -> Result<Vec<String>, InterpreterError> {
+        self.output.borrow_mut().capture = Some(Vec::new());
+        let result = self.instance.interpret_buffer(STACK_DUMP).into_result();
+        let captured = self.output.borrow_mut().capture.take().unwrap_or_default();
+        result?;
+
+        Ok(String::from_utf8_lossy(&captured)
+            .lines()
+            .map(|line| line.to_owned())
+            .collect())
+    }
+}
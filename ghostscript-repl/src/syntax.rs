@@ -0,0 +1,89 @@
+// Decides whether the collected input should be sent to the interpreter yet.
+// Input is incomplete while a procedure body or a string literal is left open,
+// in which case the prompt keeps reading continuation lines.
+// Anything else, including unbalanced closing delimiters, is left for the interpreter to report.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut bytes = source.bytes().peekable();
+    let mut procedures: isize = 0;
+
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                // Comment until the end of line.
+                while let Some(c) = bytes.next() {
+                    if c == b'\n' || c == b'\r' {
+                        break;
+                    }
+                }
+            },
+            b'(' => {
+                let mut depth = 1usize;
+                while depth > 0 {
+                    match bytes.next() {
+                        Some(b'\\') => {
+                            bytes.next();
+                        },
+                        Some(b'(') => depth += 1,
+                        Some(b')') => depth -= 1,
+                        Some(_) => {},
+                        None => return true,
+                    }
+                }
+            },
+            b'<' => match bytes.peek().cloned() {
+                // Dictionary mark.
+                Some(b'<') => {
+                    bytes.next();
+                },
+                // ASCII85 string.
+                Some(b'~') => {
+                    bytes.next();
+                    let mut tilde = false;
+                    loop {
+                        match bytes.next() {
+                            Some(b'>') if tilde => break,
+                            Some(c) => tilde = c == b'~',
+                            None => return true,
+                        }
+                    }
+                },
+                // Hex string.
+                _ => loop {
+                    match bytes.next() {
+                        Some(b'>') => break,
+                        Some(_) => {},
+                        None => return true,
+                    }
+                },
+            },
+            b'{' => procedures += 1,
+            b'}' => procedures -= 1,
+            _ => {},
+        }
+    }
+
+    procedures > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_incomplete;
+
+    #[test]
+    fn open_procedures_and_strings_need_more_input() {
+        assert!(!is_incomplete("1 2 add"));
+        assert!(!is_incomplete("/sq { dup mul } def"));
+        assert!(!is_incomplete("<< /a 1 >> (str) <48 65> <~87cURD]i,\"Ebo80~>"));
+        assert!(!is_incomplete("} % stray closing brace is for the interpreter to report"));
+        assert!(!is_incomplete("(nested (parens) and \\) escapes)"));
+        assert!(!is_incomplete("% { comments are ignored ("));
+
+        assert!(is_incomplete("/sq { dup mul"));
+        assert!(is_incomplete("{ { } "));
+        assert!(is_incomplete("(unterminated (nested) string"));
+        assert!(is_incomplete("(escaped close \\)"));
+        assert!(is_incomplete("<48 65"));
+        assert!(is_incomplete("<~87cURD~"));
+        assert!(is_incomplete("{ (}) "));
+    }
+}
@@ -39,7 +39,7 @@ fn main() {
             // Interpreter failed to build or run. The user_data is returned to us still.
             eprintln!("I'm just a NoCallback: {:?}", e.user_data);
             // As well as details about which part of the build process failed.
-            panic!("Error building instance: {:?}", e.kind_and_code());
+            panic!("Error building instance: {}", e);
         },
     }
 
@@ -0,0 +1,44 @@
+extern crate ghostscript;
+use ghostscript as gs;
+use gs::builder::GhostscriptBuilder;
+use gs::callback::panic::PanicCallback;
+use gs::callback::stdio::StdioCallback;
+use gs::interpreter::Interpreter;
+
+// Borrows a buffer from main()'s stack frame, instead of owning one.
+#[derive(Debug)]
+struct Sink<'a>(&'a mut Vec<u8>);
+
+impl<'a> PanicCallback for Sink<'a> {}
+
+impl<'a> StdioCallback for Sink<'a> {
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        self.0.extend_from_slice(buf);
+        buf.len()
+    }
+}
+
+fn main() {
+    let mut output = Vec::new();
+
+    {
+        let mut sink = Sink(&mut output);
+
+        let mut builder = GhostscriptBuilder::new();
+        builder
+            .with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET"])
+            .with_stdout(true);
+
+        // The instance only lives inside the closure, so it can't outlive the borrowed buffer.
+        builder
+            .scope(&mut sink, |instance| {
+                instance
+                    .interpret_buffer(b"(Hello from a scoped instance) =\n")
+                    .into_result()
+            })
+            .expect("Interpreter failed to start")
+            .expect("Interpreter failed to run the program");
+    }
+
+    print!("Captured: {}", String::from_utf8_lossy(&output));
+}
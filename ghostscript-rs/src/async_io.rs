@@ -0,0 +1,396 @@
+// Async counterparts of GhostscriptWorker jobs, for tokio based services.
+//
+// The interpreter still runs on the worker thread, so the runtime is never blocked by it.
+// Every job gets its own cancellation token, linked to the one of the instance, if any.
+// Dropping a JobFuture before it completes cancels the job through the poll callback,
+// or skips it altogether, if it hasn't started yet.
+
+use DefaultEncoding;
+use builder::BuilderResult;
+use callback::CallbackSafe;
+use cancel::CancellationToken;
+use encoding::StringEncoding;
+use instance::Ghostscript;
+use interpreter::{InputLocation, Interpreter, InterpreterResult};
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::PollSender;
+use worker::{GhostscriptWorker, WorkerError};
+
+// Same chunk size as the interpreter takes in one run_string() call.
+const MAX_CHUNK_LEN: usize = 65_535;
+
+pub struct JobFuture<R> {
+    result: oneshot::Receiver<R>,
+    token: CancellationToken,
+    completed: bool,
+}
+
+impl<R> JobFuture<R> {
+    pub fn cancel(&self) {
+        self.token.cancel()
+    }
+}
+
+impl<R> fmt::Debug for JobFuture<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JobFuture")
+            .field("token", &self.token)
+            .field("completed", &self.completed)
+            .finish()
+    }
+}
+
+impl<R> Future for JobFuture<R> {
+    type Output = Result<R, WorkerError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match Pin::new(&mut self.result).poll(cx) {
+            Poll::Ready(result) => {
+                self.completed = true;
+                Poll::Ready(result.map_err(|_| WorkerError::Disconnected))
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<R> Drop for JobFuture<R> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.token.cancel();
+        }
+    }
+}
+
+pub struct AsyncGhostscript<Q, E = DefaultEncoding> {
+    worker: GhostscriptWorker<Q, E>,
+}
+
+impl<Q, E> Clone for AsyncGhostscript<Q, E> {
+    fn clone(&self) -> Self {
+        AsyncGhostscript {
+            worker: self.worker.clone(),
+        }
+    }
+}
+
+impl<Q, E> fmt::Debug for AsyncGhostscript<Q, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncGhostscript")
+            .field("worker", &self.worker)
+            .finish()
+    }
+}
+
+impl<Q, E> From<GhostscriptWorker<Q, E>> for AsyncGhostscript<Q, E> {
+    fn from(worker: GhostscriptWorker<Q, E>) -> Self {
+        AsyncGhostscript { worker }
+    }
+}
+
+impl<Q: 'static, E: StringEncoding + 'static> AsyncGhostscript<Q, E> {
+    // Resolves once the instance is built on its worker thread.
+    pub fn spawn<F>(build: F) -> impl Future<Output = Result<Self, WorkerError>>
+    where
+        F: FnOnce() -> BuilderResult<Q, E> + Send + 'static,
+    {
+        let (started, startup) = oneshot::channel();
+        let worker = GhostscriptWorker::spawn_notify(build, move |result| {
+            let _ = started.send(result);
+        });
+        Spawn {
+            worker: Some(worker),
+            startup,
+        }
+    }
+
+    pub fn worker(&self) -> &GhostscriptWorker<Q, E> {
+        &self.worker
+    }
+
+    pub fn run<R, F>(&self, job: F) -> JobFuture<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Ghostscript<Q, E>) -> R + Send + 'static,
+    {
+        let (result, receiver) = oneshot::channel();
+        let token = CancellationToken::new();
+        let job_token = token.clone();
+
+        self.worker.submit(Box::new(move |instance: &mut Ghostscript<Q, E>| {
+            if job_token.is_cancelled() {
+                debug!("Skipping ghostscript job, that was cancelled before it started");
+                return;
+            }
+            let previous = instance.job_control.token.take();
+            instance.job_control.token = Some(match previous {
+                Some(ref parent) => job_token.linked_to(parent),
+                None => job_token,
+            });
+            let output = job(instance);
+            instance.job_control.token = previous;
+            let _ = result.send(output);
+        }));
+
+        JobFuture {
+            result: receiver,
+            token,
+            completed: false,
+        }
+    }
+
+    pub fn interpret_buffer(&self, buffer: Vec<u8>) -> JobFuture<InterpreterResult> {
+        self.run(move |instance| instance.interpret_buffer(&buffer))
+    }
+
+    pub fn interpret_file<S>(&self, file_name: S) -> JobFuture<InterpreterResult>
+    where
+        S: AsRef<E::RustType> + Send + 'static,
+    {
+        self.run(move |instance| instance.interpret_file(&file_name))
+    }
+
+    // See GhostscriptWorker::render().
+    pub fn render<S, R, F>(&self, file_name: S, collect: F) -> JobFuture<(InterpreterResult, R)>
+    where
+        Q: CallbackSafe,
+        S: AsRef<E::RustType> + Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut Q::Target) -> R + Send + 'static,
+    {
+        self.run(move |instance| {
+            let result = instance.interpret_file(&file_name);
+            (result, collect(instance.user_data_mut()))
+        })
+    }
+
+    // Occupies the worker until the stream is shut down or dropped.
+    // At most `capacity` chunks are queued, before writes start to wait for the interpreter.
+    // Capacity 0 is taken as 1, since a chunk has to be queued to be handed over at all.
+    pub fn open_stream(&self, capacity: usize) -> AsyncInterpreterStream {
+        let (chunks, mut chunk_queue) = mpsc::channel::<Vec<u8>>(::std::cmp::max(capacity, 1));
+
+        let result = self.run(move |instance| {
+            let mut stream = match instance.open_interpreter_stream() {
+                Ok(stream) => stream,
+                Err(e) => return (e.into(), None),
+            };
+            while let Some(chunk) = chunk_queue.blocking_recv() {
+                // Interpreter errors are visible in the close result.
+                let _ = stream.write_all(&chunk);
+                if stream.is_completed() {
+                    break;
+                }
+            }
+            stream.close_with_location()
+        });
+
+        AsyncInterpreterStream {
+            chunks: PollSender::new(chunks),
+            result,
+            closed: None,
+        }
+    }
+}
+
+struct Spawn<Q, E> {
+    worker: Option<Result<GhostscriptWorker<Q, E>, WorkerError>>,
+    startup: oneshot::Receiver<Result<(), WorkerError>>,
+}
+
+impl<Q, E> Future for Spawn<Q, E> {
+    type Output = Result<AsyncGhostscript<Q, E>, WorkerError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Some(Err(e)) = self.worker.take() {
+            return Poll::Ready(Err(e));
+        }
+        let started = match Pin::new(&mut self.startup).poll(cx) {
+            Poll::Ready(started) => started.unwrap_or(Err(WorkerError::Disconnected)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let worker = self.worker
+            .take()
+            .expect("Bug! Spawn polled after completion")
+            .expect("Bug! Spawn error was handled above");
+        Poll::Ready(started.map(|()| AsyncGhostscript { worker }))
+    }
+}
+
+// Interpreter stream, that waits for the interpreter instead of blocking the runtime, when it lags behind.
+// Writes after the interpreter has completed fail with BrokenPipe. The result is available after shutdown.
+pub struct AsyncInterpreterStream {
+    chunks: PollSender<Vec<u8>>,
+    result: JobFuture<(InterpreterResult, Option<InputLocation>)>,
+    closed: Option<(InterpreterResult, Option<InputLocation>)>,
+}
+
+impl fmt::Debug for AsyncInterpreterStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncInterpreterStream")
+            .field("result", &self.result)
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
+impl AsyncInterpreterStream {
+    pub fn result(&self) -> Option<InterpreterResult> {
+        self.closed.map(|(result, _)| result)
+    }
+
+    pub fn error_location(&self) -> Option<InputLocation> {
+        self.closed.and_then(|(_, location)| location)
+    }
+}
+
+fn interpreter_completed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "ghostscript interpreter stream is completed")
+}
+
+impl AsyncWrite for AsyncInterpreterStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.chunks.poll_reserve(cx) {
+            Poll::Ready(Ok(())) => {},
+            Poll::Ready(Err(_)) => return Poll::Ready(Err(interpreter_completed())),
+            Poll::Pending => return Poll::Pending,
+        }
+        let len = ::std::cmp::min(buf.len(), MAX_CHUNK_LEN);
+        match self.chunks.send_item(buf[..len].to_vec()) {
+            Ok(()) => Poll::Ready(Ok(len)),
+            Err(_) => Poll::Ready(Err(interpreter_completed())),
+        }
+    }
+
+    // Written chunks are already handed over to the worker thread.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // Ends the program, and waits for the interpreter to finish it.
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.chunks.close();
+        if self.closed.is_none() {
+            match Pin::new(&mut self.result).poll(cx) {
+                Poll::Ready(Ok(closed)) => self.closed = Some(closed),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::consts;
+    use interpreter::PostscriptExit;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Wake, Waker};
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn job_future<R>() -> (oneshot::Sender<R>, JobFuture<R>) {
+        let (sender, result) = oneshot::channel();
+        let future = JobFuture {
+            result,
+            token: CancellationToken::new(),
+            completed: false,
+        };
+        (sender, future)
+    }
+
+    #[test]
+    fn dropping_pending_job_future_cancels_the_job() {
+        let (_sender, future) = job_future::<()>();
+        let token = future.token.clone();
+        drop(future);
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn dropping_completed_job_future_doesnt_cancel() {
+        let waker = Waker::from(Arc::new(CountingWaker::default()));
+        let mut cx = Context::from_waker(&waker);
+
+        let (sender, mut future) = job_future();
+        let token = future.token.clone();
+        sender.send(42).unwrap();
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Ok(42)));
+        drop(future);
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn stream_writes_wait_for_the_interpreter_to_take_queued_chunks() {
+        let counter = Arc::new(CountingWaker::default());
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let (chunks, mut chunk_queue) = mpsc::channel(1);
+        let (result, job) = job_future();
+        let mut stream = AsyncInterpreterStream {
+            chunks: PollSender::new(chunks),
+            result: job,
+            closed: None,
+        };
+
+        match Pin::new(&mut stream).poll_write(&mut cx, b"1 2 add") {
+            Poll::Ready(Ok(7)) => {},
+            other => panic!("First chunk wasn't queued: {:?}", other),
+        }
+        assert!(Pin::new(&mut stream).poll_write(&mut cx, b" pop").is_pending());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+
+        assert_eq!(chunk_queue.try_recv().unwrap(), b"1 2 add".to_vec());
+        assert!(counter.0.load(Ordering::SeqCst) > 0);
+        match Pin::new(&mut stream).poll_write(&mut cx, b" pop") {
+            Poll::Ready(Ok(4)) => {},
+            other => panic!("Second chunk wasn't queued after the first was taken: {:?}", other),
+        }
+
+        let closed = (InterpreterResult(consts::OK, PostscriptExit::Success), None);
+        result.send(closed).unwrap();
+        match Pin::new(&mut stream).poll_shutdown(&mut cx) {
+            Poll::Ready(Ok(())) => {},
+            other => panic!("Shutdown didn't complete: {:?}", other),
+        }
+        assert_eq!(stream.result(), Some(closed.0));
+        assert_eq!(chunk_queue.try_recv().unwrap(), b" pop".to_vec());
+        assert!(chunk_queue.try_recv().is_err());
+    }
+
+    #[test]
+    fn stream_writes_fail_once_the_interpreter_is_gone() {
+        let waker = Waker::from(Arc::new(CountingWaker::default()));
+        let mut cx = Context::from_waker(&waker);
+
+        let (chunks, chunk_queue) = mpsc::channel(1);
+        let (_result, job) = job_future();
+        let mut stream = AsyncInterpreterStream {
+            chunks: PollSender::new(chunks),
+            result: job,
+            closed: None,
+        };
+        drop(chunk_queue);
+        match Pin::new(&mut stream).poll_write(&mut cx, b"1 2 add") {
+            Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::BrokenPipe => {},
+            other => panic!("Write after completion didn't fail: {:?}", other),
+        }
+    }
+}
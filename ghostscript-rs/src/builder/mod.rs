@@ -1,16 +1,32 @@
-use DefaultEncoding as Encoding;
+use DefaultEncoding;
 use boolinator::Boolinator;
+use callback::closure::{ClosureCallbacks, Frame};
+use callback::handler::{DynHandler, GhostscriptHandler, HandlerCapabilities};
+use callback::panic::PanicMode;
+use cancel::{CancellationToken, Deadline};
 use device_list;
 use encoding::StringEncoding;
-use error::ErrCode;
+use error::{ErrCode, GsError};
 use gs_sys;
 use instance;
+use interpreter::UserErrors;
+use job::JobControl;
+use limits::ResourceLimits;
+use process::{GhostscriptProcess, ProcessCallbacks};
+use std::error::Error;
+use std::ffi::{CString, OsStr};
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
 use std::os::raw::{c_char, c_void};
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum BuilderErrorKind {
+    // Only with "dlopen". BuilderError::load_error tells, which libraries were tried.
+    LibraryNotFound,
     Creation,
     ArgumentEncoding,
     DefaultDeviceList,
@@ -18,50 +34,120 @@ pub enum BuilderErrorKind {
     PollCallback,
     StdioCallback,
     Initialization,
+    Process,
+    Busy,
+}
+
+impl BuilderErrorKind {
+    pub(crate) fn context(&self) -> &'static str {
+        match *self {
+            BuilderErrorKind::LibraryNotFound => "failed to load ghostscript library",
+            BuilderErrorKind::Creation => "failed to create ghostscript instance",
+            BuilderErrorKind::ArgumentEncoding => "failed to set argument encoding",
+            BuilderErrorKind::DefaultDeviceList => "failed to set default device list",
+            BuilderErrorKind::DisplayCallback => "failed to set display callback",
+            BuilderErrorKind::PollCallback => "failed to set poll callback",
+            BuilderErrorKind::StdioCallback => "failed to set stdio callbacks",
+            BuilderErrorKind::Initialization => "failed to initialize interpreter",
+            BuilderErrorKind::Process => "failed to start ghostscript process",
+            BuilderErrorKind::Busy => "failed to wait for another ghostscript instance",
+        }
+    }
+}
+
+impl fmt::Display for BuilderErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.context())
+    }
 }
 
 #[derive(Debug)]
 pub struct BuilderError<T> {
     pub kind: BuilderErrorKind,
-    pub code: ErrCode,
+    pub error: GsError,
     pub user_data: T,
+    // Set with BuilderErrorKind::LibraryNotFound.
+    #[cfg(feature = "dlopen")]
+    pub load_error: Option<::dynamic::LoadError>,
 }
 
 impl<T> BuilderError<T> {
-    pub fn new(kind: BuilderErrorKind, code: ErrCode, user_data: T) -> Self {
+    pub fn new(kind: BuilderErrorKind, error: GsError, user_data: T) -> Self {
         BuilderError {
             kind,
-            code,
+            error,
             user_data,
+            #[cfg(feature = "dlopen")]
+            load_error: None,
+        }
+    }
+
+    #[cfg(feature = "dlopen")]
+    pub(crate) fn library_not_found(load_error: ::dynamic::LoadError, user_data: T) -> Self {
+        BuilderError {
+            load_error: Some(load_error),
+            ..BuilderError::new(BuilderErrorKind::LibraryNotFound, GsError::Fatal, user_data)
         }
     }
 
+    pub fn kind_and_error(&self) -> (BuilderErrorKind, GsError) {
+        (self.kind, self.error)
+    }
+
     pub fn kind_and_code(&self) -> (BuilderErrorKind, ErrCode) {
-        (self.kind, self.code)
+        (self.kind, self.error.code())
+    }
+}
+
+impl<T> fmt::Display for BuilderError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "dlopen")]
+        {
+            if let Some(ref load_error) = self.load_error {
+                return write!(f, "Ghostscript builder {}: {}", self.kind, load_error);
+            }
+        }
+        write!(f, "Ghostscript builder {}: {}", self.kind, self.error)
+    }
+}
+
+impl<T: fmt::Debug> Error for BuilderError<T> {
+    fn description(&self) -> &str {
+        self.kind.context()
+    }
+
+    fn source(&self) -> Option<&(Error + 'static)> {
+        #[cfg(feature = "dlopen")]
+        {
+            if let Some(ref load_error) = self.load_error {
+                return Some(load_error);
+            }
+        }
+        Some(&self.error)
     }
 }
 
 #[derive(Debug)]
-pub enum BuilderResult<T> {
-    Running(::instance::Ghostscript<T>),
+pub enum BuilderResult<T, E = DefaultEncoding> {
+    Running(::instance::Ghostscript<T, E>),
     Quit(T),
     Failed(BuilderError<T>),
 }
 
-impl<T> BuilderResult<T> {
-    pub fn running(self) -> Result<::instance::Ghostscript<T>, BuilderError<T>> {
+impl<T, E> BuilderResult<T, E> {
+    pub fn running(self) -> Result<::instance::Ghostscript<T, E>, BuilderError<T>> {
         match self {
             BuilderResult::Running(instance) => Ok(instance),
             BuilderResult::Quit(user_data) => Err(BuilderError::new(
                 BuilderErrorKind::Initialization,
-                ::error::consts::QUIT,
+                GsError::Quit,
                 user_data,
             )),
             BuilderResult::Failed(be) => Err(be),
         }
     }
 
-    pub fn has_quit(self) -> Result<T, Result<::instance::Ghostscript<T>, BuilderError<T>>> {
+    pub fn has_quit(self) -> Result<T, Result<::instance::Ghostscript<T, E>, BuilderError<T>>> {
         match self {
             BuilderResult::Quit(user_data) => Ok(user_data),
             BuilderResult::Running(instance) => Err(Ok(instance)),
@@ -70,19 +156,42 @@ impl<T> BuilderResult<T> {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct GhostscriptBuilder<T> {
+#[derive(Debug)]
+pub struct GhostscriptBuilder<T, E: StringEncoding = DefaultEncoding> {
     default_device_list: Option<device_list::DeviceList>,
     display_callback: Option<Arc<gs_sys::display::DisplayCallback>>,
     poll_callback: Option<::callback::poll::ffi_callbacks::Poll>,
     stdin_callback: Option<::callback::stdio::ffi_callbacks::Input>,
     stdout_callback: Option<::callback::stdio::ffi_callbacks::Output>,
     stderr_callback: Option<::callback::stdio::ffi_callbacks::Output>,
-    init_params: Vec<<Encoding as StringEncoding>::FfiType>,
-    _pd: ::std::marker::PhantomData<T>,
+    init_params: Vec<E::FfiType>,
+    user_errors: UserErrors,
+    job_control: JobControl,
+    // Handlers registered with on_*(). Only used by GhostscriptBuilder<ClosureCallbacks>.
+    closures: ClosureCallbacks,
+    _pd: PhantomData<(T, E)>,
+}
+
+// Not derived, since neither user data nor the encoding type need to be Clone themselves.
+impl<T, E: StringEncoding> Clone for GhostscriptBuilder<T, E> {
+    fn clone(&self) -> Self {
+        GhostscriptBuilder {
+            default_device_list: self.default_device_list.clone(),
+            display_callback: self.display_callback.clone(),
+            poll_callback: self.poll_callback,
+            stdin_callback: self.stdin_callback,
+            stdout_callback: self.stdout_callback,
+            stderr_callback: self.stderr_callback,
+            init_params: self.init_params.clone(),
+            user_errors: self.user_errors,
+            job_control: self.job_control.clone(),
+            closures: self.closures.clone(),
+            _pd: PhantomData,
+        }
+    }
 }
 
-impl<T> ::std::default::Default for GhostscriptBuilder<T> {
+impl<T, E: StringEncoding> ::std::default::Default for GhostscriptBuilder<T, E> {
     fn default() -> Self {
         GhostscriptBuilder {
             default_device_list: None,
@@ -92,19 +201,31 @@ impl<T> ::std::default::Default for GhostscriptBuilder<T> {
             stdout_callback: None,
             stderr_callback: None,
             init_params: Vec::new(),
-            _pd: ::std::marker::PhantomData::<T>,
+            user_errors: UserErrors::default(),
+            job_control: JobControl::default(),
+            closures: ClosureCallbacks::default(),
+            _pd: PhantomData::<(T, E)>,
         }
     }
 }
 
-impl<T> GhostscriptBuilder<T>
-where
-    T: Sized,
-{
+impl<T> GhostscriptBuilder<T> {
     pub fn new() -> Self {
         GhostscriptBuilder::default()
     }
 
+    // Selects an argument encoding other than the default UTF-8,
+    // e.g. GhostscriptBuilder::with_encoding::<encoding::Local>() for passing raw OsStr file names on Unix.
+    pub fn with_encoding<E: StringEncoding>() -> GhostscriptBuilder<T, E> {
+        GhostscriptBuilder::default()
+    }
+}
+
+impl<T, E> GhostscriptBuilder<T, E>
+where
+    T: Sized,
+    E: StringEncoding,
+{
     fn ensure_disp_callback(&mut self) -> &mut Arc<gs_sys::display::DisplayCallback>
     where
         T: ::callback::display::DisplayCallback,
@@ -172,14 +293,29 @@ where
         self
     }
 
+    // Replaces a reader set with with_stdin_reader().
     pub fn with_stdin(&mut self, do_it: bool) -> &mut Self
     where
         T: ::callback::stdio::StdioCallback,
     {
+        self.job_control.stdin = None;
         self.stdin_callback = do_it.as_some(::callback::stdio::ffi_callbacks::stdin_callback::<T>);
         self
     }
 
+    // Feeds the reader to the interpreter as its stdin, e.g. for "-" input file.
+    // Takes the place of StdioCallback::read_stdin() set with with_stdin().
+    // Only the next instance built gets the reader, later ones (also from clones of this builder) see EOF.
+    pub fn with_stdin_reader<R: Read + Send + 'static>(&mut self, reader: Option<R>) -> &mut Self {
+        use callback::stdio::reader::SharedReader;
+        self.job_control.stdin = reader.map(SharedReader::new);
+        self.stdin_callback = self.job_control
+            .stdin
+            .as_ref()
+            .map(|_| ::callback::stdio::ffi_callbacks::reader_stdin_callback as ::callback::stdio::ffi_callbacks::Input);
+        self
+    }
+
     pub fn with_stdout(&mut self, do_it: bool) -> &mut Self
     where
         T: ::callback::stdio::StdioCallback,
@@ -196,17 +332,91 @@ where
         self
     }
 
-    pub fn with_init_params<Q: AsRef<str>, I: IntoIterator<Item = Q>>(&mut self, params: I) -> &mut Self {
+    pub fn with_init_params<Q: AsRef<E::RustType>, I: IntoIterator<Item = Q>>(&mut self, params: I) -> &mut Self {
         self.init_params = params
             .into_iter()
-            .map(|s| Encoding::from_rust_to_ffi(s.as_ref()))
+            .map(E::from_rust_to_ffi)
             .collect();
         self
     }
 
-    #[cfg_attr(feature = "cargo-clippy", allow(let_unit_value))]
-    pub fn build<Q: ::callback::CallbackSafe<Target = T>>(&self, mut user_data: Q) -> BuilderResult<Q> {
-        let lock = ::instance::lock::get_lock();
+    // Keeps the params given to with_init_params(), for wrappers, that need ghostscript started in a certain mode.
+    pub(crate) fn add_init_param<Q: AsRef<E::RustType>>(&mut self, param: Q) -> &mut Self {
+        self.init_params.push(E::from_rust_to_ffi(param));
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn init_params(&self) -> &[E::FfiType] {
+        &self.init_params
+    }
+
+    pub fn with_user_errors(&mut self, user_errors: UserErrors) -> &mut Self {
+        self.user_errors = user_errors;
+        self
+    }
+
+    // Cancellation token, timeout, deadline and resource limits apply to initialization in build(),
+    // and are inherited by the built instance for its interpreter jobs.
+    pub fn with_cancellation_token(&mut self, token: Option<CancellationToken>) -> &mut Self {
+        self.job_control.token = token;
+        self
+    }
+
+    pub fn with_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.job_control.timeout = timeout;
+        self
+    }
+
+    pub fn with_deadline(&mut self, deadline: Option<Deadline>) -> &mut Self {
+        self.job_control.deadline = deadline;
+        self
+    }
+
+    pub fn with_resource_limits(&mut self, limits: ResourceLimits) -> &mut Self {
+        self.job_control.limits = limits;
+        self
+    }
+
+    // Whether callback panics abort (by default) or are resumed in the caller of build()/interpret_*().
+    pub fn with_panic_mode(&mut self, panic_mode: PanicMode) -> &mut Self {
+        self.job_control.panic_mode = panic_mode;
+        self
+    }
+
+    #[allow(clippy::unit_arg)]
+    pub fn build<Q: ::callback::CallbackSafe<Target = T>>(&self, user_data: Q) -> BuilderResult<Q, E> {
+        self.build_locked(::instance::lock::get_lock(), user_data)
+    }
+
+    // Fails with BuilderErrorKind::Busy, unless the "synchronized" lock can be taken right away.
+    pub fn try_build<Q: ::callback::CallbackSafe<Target = T>>(&self, user_data: Q) -> BuilderResult<Q, E> {
+        self.build_timeout(Duration::from_secs(0), user_data)
+    }
+
+    // Waits for the "synchronized" lock for at most the timeout, and fails with BuilderErrorKind::Busy
+    // and GsError::InstanceBusy after that.
+    // Waiting builds take the lock in the order they came.
+    pub fn build_timeout<Q: ::callback::CallbackSafe<Target = T>>(&self, timeout: Duration, user_data: Q) -> BuilderResult<Q, E> {
+        match ::instance::lock::get_lock_timeout(Some(timeout)) {
+            Some(lock) => self.build_locked(lock, user_data),
+            None => BuilderResult::Failed(BuilderError::new(BuilderErrorKind::Busy, GsError::InstanceBusy, user_data)),
+        }
+    }
+
+    fn build_locked<Q>(&self, lock: ::instance::lock::LockType, mut user_data: Q) -> BuilderResult<Q, E>
+    where
+        Q: ::callback::CallbackSafe<Target = T>,
+    {
+        #[cfg(feature = "dlopen")]
+        {
+            if let Err(e) = gs_sys::dynamic::load_default() {
+                return BuilderResult::Failed(BuilderError::library_not_found(e, user_data));
+            }
+        }
+
+        let job_control = self.job_control.for_instance();
+        let job = job_control.start();
 
         let mut instance = ::std::ptr::null_mut();
 
@@ -215,12 +425,8 @@ where
                 let data_ptr: *mut T = user_data.as_stable_mut();
                 gs_sys::ffi::gsapi_new_instance(&mut instance, data_ptr as *mut c_void)
             };
-            if err != gs_sys::GS_OK {
-                return BuilderResult::Failed(BuilderError::new(
-                    BuilderErrorKind::Creation,
-                    ErrCode(err),
-                    user_data,
-                ));
+            if let Err(e) = ErrCode(err).to_result() {
+                return BuilderResult::Failed(BuilderError::new(BuilderErrorKind::Creation, e, user_data));
             }
         }
 
@@ -230,18 +436,18 @@ where
             lock,
             instance,
             initialized: false,
+            poisoned: false,
+            user_errors: self.user_errors,
+            job_control,
             user_data: Some(user_data),
             display_callback: None,
+            _encoding: PhantomData,
         };
 
         unsafe {
-            let err = gs_sys::ffi::gsapi_set_arg_encoding(instance.instance, Encoding::GHOSTSCRIPT_ENCODING);
-            if err != gs_sys::GS_OK {
-                return BuilderResult::Failed(BuilderError::new(
-                    BuilderErrorKind::ArgumentEncoding,
-                    ErrCode(err),
-                    instance.into_inner(),
-                ));
+            let err = gs_sys::ffi::gsapi_set_arg_encoding(instance.instance, E::GHOSTSCRIPT_ENCODING);
+            if let Err(e) = ErrCode(err).to_result() {
+                return BuilderResult::Failed(BuilderError::new(BuilderErrorKind::ArgumentEncoding, e, instance.into_inner()));
             }
         }
 
@@ -253,12 +459,8 @@ where
                     default_device_list.as_ptr() as _,
                     default_device_list.len() as _,
                 );
-                if err != gs_sys::GS_OK {
-                    return BuilderResult::Failed(BuilderError::new(
-                        BuilderErrorKind::DefaultDeviceList,
-                        ErrCode(err),
-                        instance.into_inner(),
-                    ));
+                if let Err(e) = ErrCode(err).to_result() {
+                    return BuilderResult::Failed(BuilderError::new(BuilderErrorKind::DefaultDeviceList, e, instance.into_inner()));
                 }
             }
         }
@@ -269,27 +471,20 @@ where
                     instance.instance,
                     display_callback.as_ref() as *const gs_sys::display::DisplayCallback as *mut _,
                 );
-                if err != gs_sys::GS_OK {
-                    return BuilderResult::Failed(BuilderError::new(
-                        BuilderErrorKind::DisplayCallback,
-                        ErrCode(err),
-                        instance.into_inner(),
-                    ));
+                if let Err(e) = ErrCode(err).to_result() {
+                    return BuilderResult::Failed(BuilderError::new(BuilderErrorKind::DisplayCallback, e, instance.into_inner()));
                 }
                 instance.display_callback = Some(display_callback);
             }
         }
 
-        if self.poll_callback.is_some() {
-            unsafe {
-                let err = gs_sys::ffi::gsapi_set_poll(instance.instance as *mut c_void, self.poll_callback);
-                if err != gs_sys::GS_OK {
-                    return BuilderResult::Failed(BuilderError::new(
-                        BuilderErrorKind::PollCallback,
-                        ErrCode(err),
-                        instance.into_inner(),
-                    ));
-                }
+        // Without user's poll callback, install our own to support cancellation of jobs.
+        let poll_callback = self.poll_callback
+            .unwrap_or(::callback::poll::ffi_callbacks::job_control_poll_callback);
+        unsafe {
+            let err = gs_sys::ffi::gsapi_set_poll(instance.instance as *mut c_void, Some(poll_callback));
+            if let Err(e) = ErrCode(err).to_result() {
+                return BuilderResult::Failed(BuilderError::new(BuilderErrorKind::PollCallback, e, instance.into_inner()));
             }
         }
 
@@ -301,12 +496,8 @@ where
                     self.stdout_callback,
                     self.stderr_callback,
                 );
-                if err != gs_sys::GS_OK {
-                    return BuilderResult::Failed(BuilderError::new(
-                        BuilderErrorKind::StdioCallback,
-                        ErrCode(err),
-                        instance.into_inner(),
-                    ));
+                if let Err(e) = ErrCode(err).to_result() {
+                    return BuilderResult::Failed(BuilderError::new(BuilderErrorKind::StdioCallback, e, instance.into_inner()));
                 }
             }
         }
@@ -314,20 +505,21 @@ where
         let mut init_ptrs: Vec<*const c_char> = Vec::new();
 
         // First parameter is always ignored, fill it with an empty one.
-        let empty_arg = Encoding::from_rust_to_ffi("");
+        // This and display handle are plain ASCII, which is valid in any encoding.
+        let empty_arg = CString::default();
         init_ptrs.push(empty_arg.as_ptr());
 
         // Make second parameter be display handle, if we need one.
         // Making it last doesn't work, if args contain file names.
         let display_handle_arg = {
             self.display_callback.as_ref().map(|_| {
-                Encoding::from_rust_to_ffi(&Self::format_display_handle_string(
+                CString::new(Self::format_display_handle_string(
                     instance
                         .user_data
                         .as_mut()
                         .expect("Bug! user_data is missing.")
                         .as_stable_mut(),
-                ))
+                )).expect("Bug! Display handle contains nul characters")
             })
         };
 
@@ -337,30 +529,37 @@ where
         }
 
         // Fill the rest of user arguments.
-        init_ptrs.extend(self.init_params.iter().map(|s| s.as_ptr()));
+        init_ptrs.extend(self.init_params.iter().map(|s| s.as_ref().as_ptr()));
 
         unsafe {
-            let err = gs_sys::ffi::gsapi_init_with_args(
-                instance.instance,
-                init_ptrs.len() as _,
-                init_ptrs.as_ptr() as *mut *mut _,
-            );
+            let err = {
+                let _guard = job.enter();
+                gs_sys::ffi::gsapi_init_with_args(
+                    instance.instance,
+                    init_ptrs.len() as _,
+                    init_ptrs.as_ptr() as *mut *mut _,
+                )
+            };
+            if err == gs_sys::GS_OK || err == gs_sys::error::QUIT {
+                // gsapi_exit() shall be called on instance on drop.
+                instance.initialized = true;
+            }
+            // The instance is destroyed, along with the user data, while the panic unwinds.
+            instance.resume_panic(&job);
             match err {
                 gs_sys::GS_OK => {
-                    // Success. gsapi_exit() shall be called on instance on drop.
-                    instance.initialized = true;
+                    // Success.
                 },
                 gs_sys::error::QUIT => {
                     // Regular quit during init argument processing.
                     // The instance can't be used further,
                     // and has to be de-initialized with gsapi_exit().
-                    instance.initialized = true;
                     return BuilderResult::Quit(instance.into_inner());
                 },
                 _ => {
                     return BuilderResult::Failed(BuilderError::new(
                         BuilderErrorKind::Initialization,
-                        ErrCode(err),
+                        job.map_error(GsError::from_raw(err).expect("Bug! GS_OK was handled above")),
                         instance.into_inner(),
                     ))
                 },
@@ -370,6 +569,54 @@ where
         BuilderResult::Running(instance)
     }
 
+    // Builds an instance, that borrows user data from the caller's stack frame, and runs f on it.
+    // The instance is destroyed before scope() returns, so no callback can outlive the borrow,
+    // and callbacks may write straight into local buffers without Box or Arc<Mutex<..>>.
+    // Init params, that make the interpreter quit during initialization, fail with GsError::Quit.
+    pub fn scope<'d, R, F>(&self, user_data: &'d mut T, f: F) -> Result<R, BuilderError<&'d mut T>>
+    where
+        F: FnOnce(&mut instance::Ghostscript<&'d mut T, E>) -> R,
+    {
+        let mut instance = self.build(user_data).running()?;
+        let result = f(&mut instance);
+        // gsapi_exit() may still call display callbacks, and the data is borrowed until here.
+        ::std::mem::drop(instance);
+        Ok(result)
+    }
+
+    // Runs the interpreter in a separate process of the executable, e.g. process::DEFAULT_EXECUTABLE,
+    // with the same init params, job control and stdio callbacks. See process module for what isn't supported.
+    // Display callbacks are out of scope and fail with BuilderErrorKind::DisplayCallback,
+    // pages have to come through the stdout callback from a raster device writing to %stdout.
+    pub fn build_process<Q, P>(&self, executable: P, user_data: Q) -> Result<GhostscriptProcess<Q, E>, BuilderError<Q>>
+    where
+        Q: ::callback::CallbackSafe<Target = T>,
+        P: AsRef<OsStr>,
+    {
+        if self.default_device_list.is_some() {
+            return Err(BuilderError::new(BuilderErrorKind::DefaultDeviceList, GsError::ConfigurationError, user_data));
+        }
+        if self.display_callback.is_some() {
+            return Err(BuilderError::new(BuilderErrorKind::DisplayCallback, GsError::ConfigurationError, user_data));
+        }
+        if self.stdin_callback.is_some() {
+            return Err(BuilderError::new(BuilderErrorKind::StdioCallback, GsError::ConfigurationError, user_data));
+        }
+        let callbacks = ProcessCallbacks {
+            poll: self.poll_callback,
+            stdout: self.stdout_callback,
+            stderr: self.stderr_callback,
+        };
+        GhostscriptProcess::start(
+            executable.as_ref(),
+            &self.init_params,
+            self.user_errors,
+            self.job_control.for_instance(),
+            callbacks,
+            user_data,
+        )
+    }
+
     fn format_display_handle_string(handle: *const T) -> String
     where
         T: Sized,
@@ -378,8 +625,221 @@ where
     }
 }
 
-impl GhostscriptBuilder<()> {
-    pub fn build_simple(&self) -> BuilderResult<::callback::NoCallback> {
+impl<E: StringEncoding> GhostscriptBuilder<(), E> {
+    pub fn build_simple(&self) -> BuilderResult<::callback::NoCallback, E> {
         self.build(::callback::NoCallback)
     }
 }
+
+// Callbacks without a custom user data type. Each on_*() both registers the closure and enables the callback.
+impl<E: StringEncoding> GhostscriptBuilder<ClosureCallbacks, E> {
+    pub fn on_stdout<F: FnMut(&[u8]) + Send + 'static>(&mut self, handler: F) -> &mut Self {
+        self.closures.stdout = Some(Arc::new(Mutex::new(handler)));
+        self.with_stdout(true)
+    }
+
+    // Stderr is only enabled by on_stderr() or with_stderr(true). In the latter case it goes to the stdout handler.
+    pub fn on_stderr<F: FnMut(&[u8]) + Send + 'static>(&mut self, handler: F) -> &mut Self {
+        self.closures.stderr = Some(Arc::new(Mutex::new(handler)));
+        self.with_stderr(true)
+    }
+
+    pub fn on_poll<F: FnMut() -> ErrCode + Send + 'static>(&mut self, handler: F) -> &mut Self {
+        self.closures.poll = Some(Arc::new(Mutex::new(handler)));
+        self.with_poll(true)
+    }
+
+    // Pages only come from the display device, e.g. "-sDEVICE=display" with a "-dDisplayFormat=..." param.
+    pub fn on_page<F: FnMut(&Frame) + Send + 'static>(&mut self, handler: F) -> &mut Self {
+        self.closures.page = Some(Arc::new(Mutex::new(handler)));
+        self.with_display(true)
+    }
+
+    pub fn build_closures(&self) -> BuilderResult<Box<ClosureCallbacks>, E> {
+        self.build(Box::new(self.closures.clone()))
+    }
+}
+
+// Handlers chosen at runtime. Callbacks, that the handler doesn't claim in its capabilities,
+// are not installed, regardless of earlier with_*() calls. A reader set with with_stdin_reader() is kept,
+// unless the handler reads stdin itself.
+impl<E: StringEncoding> GhostscriptBuilder<DynHandler, E> {
+    pub fn build_dyn(&self, handler: Box<GhostscriptHandler>) -> BuilderResult<Box<DynHandler>, E> {
+        self.for_capabilities(handler.capabilities())
+            .build(Box::new(DynHandler(handler)))
+    }
+
+    fn for_capabilities(&self, caps: HandlerCapabilities) -> Self {
+        let display = caps.contains(HandlerCapabilities::DISPLAY);
+
+        let mut builder = self.clone();
+        builder
+            .with_display(display)
+            .with_display_update(display && caps.contains(HandlerCapabilities::DISPLAY_UPDATE))
+            .with_display_alloc(display && caps.contains(HandlerCapabilities::DISPLAY_ALLOC))
+            .with_display_separation(display && caps.contains(HandlerCapabilities::DISPLAY_SEPARATION))
+            .with_poll(caps.contains(HandlerCapabilities::POLL))
+            .with_stdout(caps.contains(HandlerCapabilities::STDOUT))
+            .with_stderr(caps.contains(HandlerCapabilities::STDERR));
+        if caps.contains(HandlerCapabilities::STDIN) {
+            builder.with_stdin(true);
+        } else if builder.job_control.stdin.is_none() {
+            builder.with_stdin(false);
+        }
+        builder
+    }
+}
+
+// The instance can't escape scope(), so no callback runs after the borrow of the user data ends.
+#[cfg(doctest)]
+/// ```compile_fail
+/// extern crate ghostscript;
+/// let builder = ghostscript::builder::GhostscriptBuilder::<()>::new();
+/// let mut data = ();
+/// let escaped = builder.scope(&mut data, |instance| instance).unwrap();
+/// ```
+struct ScopedInstanceCannotEscape;
+
+// Tests, that run the interpreter, pass without it, when the library can't be loaded.
+#[cfg(all(test, feature = "dlopen"))]
+pub(crate) fn library_available() -> bool {
+    ::dynamic::load_default().map_err(|e| eprintln!("Skipped: {}", e)).is_ok()
+}
+
+#[cfg(all(test, not(feature = "dlopen")))]
+pub(crate) fn library_available() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use callback::panic::PanicCallback;
+    use callback::stdio::StdioCallback;
+    use interpreter::Interpreter;
+
+    #[derive(Debug)]
+    struct Sink<'a>(&'a mut Vec<u8>);
+
+    impl<'a> PanicCallback for Sink<'a> {}
+
+    impl<'a> StdioCallback for Sink<'a> {
+        fn write_stdout(&mut self, buf: &[u8]) -> usize {
+            self.0.extend_from_slice(buf);
+            buf.len()
+        }
+    }
+
+    #[test]
+    fn scoped_callbacks_write_into_stack_local_buffer() {
+        if !library_available() {
+            return;
+        }
+        let mut output = Vec::new();
+        {
+            let mut sink = Sink(&mut output);
+            let mut builder = GhostscriptBuilder::new();
+            builder
+                .with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"])
+                .with_stdout(true);
+            builder
+                .scope(&mut sink, |instance| instance.interpret_buffer(b"(scoped) print flush\n").into_result())
+                .expect("Interpreter failed to start")
+                .expect("Interpreter failed to run the program");
+        }
+        assert_eq!(output, b"scoped");
+    }
+
+    #[cfg(feature = "dlopen")]
+    #[test]
+    fn missing_library_fails_build_with_the_attempts() {
+        #[cfg(feature = "synchronized")]
+        let _serial = ::instance::lock::serialize_test();
+        let error = match GhostscriptBuilder::<()>::new().build_simple() {
+            BuilderResult::Failed(error) => error,
+            _ => return assert!(::dynamic::is_loaded()),
+        };
+        if ::dynamic::is_loaded() {
+            return;
+        }
+        assert_eq!(error.kind, BuilderErrorKind::LibraryNotFound);
+        let candidates = match ::std::env::var_os(::dynamic::LIBRARY_PATH_VAR) {
+            Some(_) => 1,
+            None => ::dynamic::DEFAULT_LIBRARY_NAMES.len(),
+        };
+        match error.load_error {
+            Some(::dynamic::LoadError::LibraryNotFound(ref attempts)) => assert_eq!(attempts.len(), candidates),
+            ref other => panic!("Unexpected load error: {:?}", other),
+        }
+        assert!(error.to_string().contains("Ghostscript library not found"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn local_encoding_passes_non_utf8_bytes_unchanged() {
+        use encoding::Local;
+        use std::os::unix::ffi::OsStrExt;
+
+        let file_name = OsStr::from_bytes(b"-sOutputFile=page-\xff\xfe.png");
+        let mut builder = GhostscriptBuilder::<()>::with_encoding::<Local>();
+        builder.with_init_params(&[file_name]);
+
+        // These are the pointers, that build() passes as argv.
+        let argv: Vec<&[u8]> = builder.init_params.iter().map(|s| s.as_ref().to_bytes()).collect();
+        assert_eq!(argv, vec![file_name.as_bytes()]);
+    }
+
+    #[test]
+    fn dyn_handler_without_stdin_keeps_only_the_reader() {
+        let mut builder = GhostscriptBuilder::<DynHandler>::new();
+        builder.with_stdin(true);
+        let dyn_builder = builder.for_capabilities(HandlerCapabilities::STDOUT);
+        assert!(dyn_builder.stdin_callback.is_none());
+
+        builder.with_stdin_reader(Some(&b"1 2 add"[..]));
+        let dyn_builder = builder.for_capabilities(HandlerCapabilities::STDOUT);
+        assert!(dyn_builder.stdin_callback.is_some() && dyn_builder.job_control.stdin.is_some());
+
+        let dyn_builder = builder.for_capabilities(HandlerCapabilities::STDIN);
+        assert!(dyn_builder.stdin_callback.is_some() && dyn_builder.job_control.stdin.is_none());
+    }
+
+    #[cfg(feature = "synchronized")]
+    #[test]
+    fn busy_lock_fails_the_build_apart_from_timed_out_jobs() {
+        let _serial = ::instance::lock::serialize_test();
+        if instance::Ghostscript::supports_multiple_instances() {
+            return;
+        }
+        let _held = ::instance::lock::exclusive();
+        let error = match GhostscriptBuilder::<()>::new().try_build(::callback::NoCallback) {
+            BuilderResult::Failed(error) => error,
+            _ => panic!("Built while the lock was held"),
+        };
+        assert_eq!(error.kind, BuilderErrorKind::Busy);
+        assert!(error.error.is_instance_busy() && !error.error.is_timed_out());
+        assert_eq!(error.error.code(), ::error::consts::INSTANCE_BUSY);
+    }
+
+    #[cfg(feature = "synchronized")]
+    #[test]
+    #[ignore = "needs libgs, run with --ignored"]
+    fn try_build_while_an_instance_is_alive_doesnt_wait() {
+        use std::time::Instant;
+
+        let mut builder = GhostscriptBuilder::<()>::new();
+        builder.with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"]);
+        let _first = builder.build_simple().running().expect("First instance failed to build");
+
+        let started = Instant::now();
+        match builder.try_build(::callback::NoCallback) {
+            BuilderResult::Failed(ref error) if error.kind == BuilderErrorKind::Busy => {
+                assert_eq!(error.error, GsError::InstanceBusy);
+                assert!(!instance::Ghostscript::supports_multiple_instances())
+            },
+            BuilderResult::Failed(error) => panic!("Unexpected build error: {}", error),
+            _ => assert!(instance::Ghostscript::supports_multiple_instances()),
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
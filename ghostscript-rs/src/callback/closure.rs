@@ -0,0 +1,201 @@
+use GS_OK;
+use callback::display::{DisplayCallback, DisplayFormat, DisplayRawDevice};
+use callback::panic::PanicCallback;
+use callback::poll::PollCallback;
+use callback::stdio::StdioCallback;
+use error::ErrCode;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// Page image, as rendered by the display device.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub raster: usize,
+    pub format: DisplayFormat,
+    pub copies: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameLayout {
+    width: usize,
+    height: usize,
+    raster: usize,
+    format: DisplayFormat,
+    pimage: *mut u8,
+}
+
+// Handlers registered with GhostscriptBuilder::on_*() closures.
+// Clones share the closures, so that a builder can build any number of instances.
+#[derive(Clone, Default)]
+pub struct ClosureCallbacks {
+    pub(crate) stdout: Option<Arc<Mutex<FnMut(&[u8]) + Send>>>,
+    pub(crate) stderr: Option<Arc<Mutex<FnMut(&[u8]) + Send>>>,
+    pub(crate) poll: Option<Arc<Mutex<FnMut() -> ErrCode + Send>>>,
+    pub(crate) page: Option<Arc<Mutex<FnMut(&Frame) + Send>>>,
+    frame: Option<FrameLayout>,
+}
+
+impl fmt::Debug for ClosureCallbacks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClosureCallbacks")
+            .field("stdout", &self.stdout.is_some())
+            .field("stderr", &self.stderr.is_some())
+            .field("poll", &self.poll.is_some())
+            .field("page", &self.page.is_some())
+            .field("frame", &self.frame)
+            .finish()
+    }
+}
+
+// A panicked closure poisons its mutex, but the closure itself is still there to call.
+fn lock<F: ?Sized>(handler: &Mutex<F>) -> MutexGuard<F> {
+    handler.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl PanicCallback for ClosureCallbacks {}
+
+impl StdioCallback for ClosureCallbacks {
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        if let Some(ref handler) = self.stdout {
+            (&mut *lock(handler))(buf);
+        }
+        buf.len()
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        match self.stderr {
+            Some(ref handler) => (&mut *lock(handler))(buf),
+            None => return self.write_stdout(buf),
+        }
+        buf.len()
+    }
+}
+
+impl PollCallback for ClosureCallbacks {
+    fn poll(&mut self) -> ErrCode {
+        match self.poll {
+            Some(ref handler) => (&mut *lock(handler))(),
+            None => GS_OK,
+        }
+    }
+}
+
+impl DisplayCallback for ClosureCallbacks {
+    fn display_close(&mut self, _device: *mut DisplayRawDevice) -> ErrCode {
+        self.frame = None;
+        GS_OK
+    }
+
+    fn display_size(
+        &mut self,
+        _device: *mut DisplayRawDevice,
+        width: usize,
+        height: usize,
+        raster: usize,
+        format: DisplayFormat,
+        pimage: *mut u8,
+    ) -> ErrCode {
+        self.frame = Some(FrameLayout {
+            width,
+            height,
+            raster,
+            format,
+            pimage,
+        });
+        GS_OK
+    }
+
+    fn display_page(&mut self, _device: *mut DisplayRawDevice, copies: u32, _flush: bool) -> ErrCode {
+        if let (Some(ref handler), Some(layout)) = (self.page.as_ref(), self.frame) {
+            // The image buffer stays valid until the next display_size() or display_close().
+            let data = unsafe { ::std::slice::from_raw_parts(layout.pimage, layout.raster * layout.height) };
+            (&mut *lock(handler))(&Frame {
+                width: layout.width,
+                height: layout.height,
+                raster: layout.raster,
+                format: layout.format,
+                copies,
+                data,
+            });
+        }
+        GS_OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::GhostscriptBuilder;
+    use error::consts;
+    use interpreter::Interpreter;
+
+    fn recorder() -> (Arc<Mutex<Vec<u8>>>, Arc<Mutex<FnMut(&[u8]) + Send>>) {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let sink = recorded.clone();
+        (recorded, Arc::new(Mutex::new(move |buf: &[u8]| sink.lock().unwrap().extend_from_slice(buf))))
+    }
+
+    #[test]
+    fn stdio_goes_to_its_closure_and_stderr_falls_back_to_stdout() {
+        let (stdout, handler) = recorder();
+        let mut callbacks = ClosureCallbacks::default();
+        callbacks.stdout = Some(handler);
+
+        assert_eq!(callbacks.write_stdout(b"out "), 4);
+        assert_eq!(callbacks.write_stderr(b"err"), 3);
+        assert_eq!(*stdout.lock().unwrap(), b"out err".to_vec());
+
+        let (stderr, handler) = recorder();
+        callbacks.stderr = Some(handler);
+        callbacks.write_stderr(b"only");
+        assert_eq!(*stderr.lock().unwrap(), b"only".to_vec());
+        assert_eq!(*stdout.lock().unwrap(), b"out err".to_vec());
+    }
+
+    #[test]
+    fn poll_returns_what_the_closure_does() {
+        let mut callbacks = ClosureCallbacks::default();
+        assert_eq!(callbacks.poll(), GS_OK);
+        callbacks.poll = Some(Arc::new(Mutex::new(|| consts::INTERRUPT)));
+        assert_eq!(callbacks.poll(), consts::INTERRUPT);
+    }
+
+    #[test]
+    fn page_closure_sees_the_frame_until_the_device_closes() {
+        let pages = Arc::new(Mutex::new(Vec::new()));
+        let seen = pages.clone();
+        let mut callbacks = ClosureCallbacks::default();
+        callbacks.page = Some(Arc::new(Mutex::new(move |frame: &Frame| {
+            seen.lock().unwrap().push((frame.width, frame.height, frame.copies, frame.data.to_vec()))
+        })));
+
+        let mut image = vec![1u8, 2, 3, 4, 5, 6];
+        let device = ::std::ptr::null_mut();
+        let format = DisplayFormat::empty();
+        assert_eq!(callbacks.display_size(device, 1, 2, 3, format, image.as_mut_ptr()), GS_OK);
+        assert_eq!(callbacks.display_page(device, 1, false), GS_OK);
+        assert_eq!(callbacks.display_close(device), GS_OK);
+        assert_eq!(callbacks.display_page(device, 1, false), GS_OK);
+
+        assert_eq!(*pages.lock().unwrap(), vec![(1, 2, 1, image)]);
+    }
+
+    #[test]
+    #[ignore = "needs libgs, run with --ignored"]
+    fn built_instance_calls_the_closures() {
+        let stdout = Arc::new(Mutex::new(Vec::new()));
+        let sink = stdout.clone();
+
+        let mut builder = GhostscriptBuilder::new();
+        builder
+            .with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"])
+            .on_stdout(move |buf| sink.lock().unwrap().extend_from_slice(buf));
+        let mut instance = builder.build_closures().running().expect("Failed to build the instance");
+
+        assert_eq!(instance.interpret_buffer(b"(closures) print flush").0, consts::OK);
+        assert_eq!(*stdout.lock().unwrap(), b"closures".to_vec());
+    }
+}
@@ -1,7 +1,10 @@
 use super::*;
 use boolinator::Boolinator;
 use callback::get_cb;
+use callback::panic::handle_callback_panic;
+use error::consts;
 use gs_sys::display as disp;
+use limits::{LimitTracker, ResourceLimit};
 use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort, c_void};
 use std::panic::catch_unwind;
 
@@ -17,11 +20,17 @@ fn get_fmt(format: c_uint) -> DisplayFormat {
     fmt
 }
 
+// Checks resource limits of the running job. Outside of a job there are no limits.
+fn within_limits<F: FnOnce(&LimitTracker) -> Result<(), ResourceLimit>>(check: F) -> bool {
+    ::job::with_current_job(|job| check(job.limits()))
+        .map_or(true, |r| r.is_ok())
+}
+
 unsafe extern "C" fn display_open<T: DisplayCallback>(handle: *mut c_void, device: *mut DisplayRawDevice) -> c_int {
     catch_unwind(|| {
         debug!("display_open! Handle: {:p}, Device: {:p}", handle, device);
         get_cb::<T>(handle).display_open(device)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_open", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_open", e))
         .raw_err()
 }
 
@@ -32,7 +41,7 @@ unsafe extern "C" fn display_preclose<T: DisplayCallback>(handle: *mut c_void, d
             handle, device
         );
         get_cb::<T>(handle).display_preclose(device)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_preclose", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_preclose", e))
         .raw_err()
 }
 
@@ -40,7 +49,7 @@ unsafe extern "C" fn display_close<T: DisplayCallback>(handle: *mut c_void, devi
     catch_unwind(|| {
         debug!("display_close! Handle: {:p}, Device: {:p}", handle, device);
         get_cb::<T>(handle).display_close(device)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_close", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_close", e))
         .raw_err()
 }
 
@@ -58,6 +67,10 @@ unsafe extern "C" fn display_presize<T: DisplayCallback>(
             handle, device, width, height, raster, format
         );
 
+        if !within_limits(|l| l.check_frame(height as usize, raster as usize)) {
+            return consts::LIMIT_CHECK;
+        }
+
         get_cb::<T>(handle).display_presize(
             device,
             width as usize,
@@ -65,7 +78,7 @@ unsafe extern "C" fn display_presize<T: DisplayCallback>(
             raster as usize,
             get_fmt(format),
         )
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_presize", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_presize", e))
         .raw_err()
 }
 
@@ -84,6 +97,10 @@ unsafe extern "C" fn display_size<T: DisplayCallback>(
             handle, device, width, height, raster, format, pimage
         );
 
+        if !within_limits(|l| l.check_frame(height as usize, raster as usize)) {
+            return consts::LIMIT_CHECK;
+        }
+
         get_cb::<T>(handle).display_size(
             device,
             width as usize,
@@ -92,7 +109,7 @@ unsafe extern "C" fn display_size<T: DisplayCallback>(
             get_fmt(format),
             pimage as *mut u8,
         )
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_size", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_size", e))
         .raw_err()
 }
 
@@ -100,7 +117,7 @@ unsafe extern "C" fn display_sync<T: DisplayCallback>(handle: *mut c_void, devic
     catch_unwind(|| {
         debug!("display_sync! Handle: {:p}, Device: {:p}", handle, device);
         get_cb::<T>(handle).display_sync(device)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_sync", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_sync", e))
         .raw_err()
 }
 
@@ -115,8 +132,11 @@ unsafe extern "C" fn display_page<T: DisplayCallback>(
             "display_page! Handle: {:p}, Device: {:p}, Copies: {}, Flush: {}",
             handle, device, copies, flush
         );
+        if !within_limits(LimitTracker::count_page) {
+            return consts::LIMIT_CHECK;
+        }
         get_cb::<T>(handle).display_page(device, copies as _, flush != 0)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_page", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_page", e))
         .raw_err()
 }
 
@@ -134,7 +154,7 @@ unsafe extern "C" fn display_update<T: DisplayUpdateCallback>(
             handle, device, x, y, w, h
         );
         get_cb::<T>(handle).display_update(device, x as usize, y as usize, w as usize, h as usize)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_update", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_update", e))
         .raw_err()
 }
 
@@ -148,9 +168,12 @@ unsafe extern "C" fn display_memalloc<T: DisplayAllocCallback>(
             "display_memalloc! Handle: {:p}, Device: {:p}, Size: {}",
             handle, device, size
         );
+        if !within_limits(|l| l.count_allocation(size as usize)) {
+            return ::std::ptr::null_mut();
+        }
         get_cb::<T>(handle).display_memalloc(device, size as usize)
     }).unwrap_or_else(|e| {
-        T::on_callback_panic(handle as *mut T, "display_memalloc", e);
+        handle_callback_panic(handle as *mut T, "display_memalloc", e);
         ::std::ptr::null_mut()
     })
 }
@@ -166,7 +189,7 @@ unsafe extern "C" fn display_memfree<T: DisplayAllocCallback>(
             handle, device, mem
         );
         get_cb::<T>(handle).display_memfree(device, mem)
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_memfree", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_memfree", e))
         .raw_err()
 }
 
@@ -202,7 +225,7 @@ unsafe extern "C" fn display_separation<T: DisplaySeparationCallback>(
             component_name,
             (c as u16, m as u16, y as u16, k as u16),
         )
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "display_separation", e))
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "display_separation", e))
         .raw_err()
 }
 
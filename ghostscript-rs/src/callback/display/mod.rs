@@ -61,25 +61,34 @@ pub trait DisplayUpdateCallback: DisplayCallback {
 
 pub trait DisplayAllocCallback: DisplayCallback {
     unsafe fn display_memalloc(&mut self, _device: *mut DisplayRawDevice, size: usize) -> *mut ::std::os::raw::c_void {
-        use std::mem::size_of;
-        let size = (size + size_of::<usize>() - 1) / size_of::<usize>();
-        let mut v: Vec<usize> = Vec::with_capacity(size + 1);
-        let ptr = v.as_mut_ptr();
-        *ptr = v.capacity();
-        ::std::mem::forget(v);
-        ptr.offset(1) as *mut ::std::os::raw::c_void
+        default_memalloc(size)
     }
 
     unsafe fn display_memfree(&mut self, _device: *mut DisplayRawDevice, mem: *mut ::std::os::raw::c_void) -> ErrCode {
-        let ptr = mem as *mut usize;
-        let ptr = ptr.offset(-1);
-        let capacity = *ptr;
-        let v = Vec::from_raw_parts(ptr, 0, capacity);
-        ::std::mem::drop(v);
-        GS_OK
+        default_memfree(mem)
     }
 }
 
+// Heap allocation, that remembers its own size. Shared with callback::handler::GhostscriptHandler.
+pub(crate) unsafe fn default_memalloc(size: usize) -> *mut ::std::os::raw::c_void {
+    use std::mem::size_of;
+    let size = (size + size_of::<usize>() - 1) / size_of::<usize>();
+    let mut v: Vec<usize> = Vec::with_capacity(size + 1);
+    let ptr = v.as_mut_ptr();
+    *ptr = v.capacity();
+    ::std::mem::forget(v);
+    ptr.offset(1) as *mut ::std::os::raw::c_void
+}
+
+pub(crate) unsafe fn default_memfree(mem: *mut ::std::os::raw::c_void) -> ErrCode {
+    let ptr = mem as *mut usize;
+    let ptr = ptr.offset(-1);
+    let capacity = *ptr;
+    let v = Vec::from_raw_parts(ptr, 0, capacity);
+    ::std::mem::drop(v);
+    GS_OK
+}
+
 pub trait DisplaySeparationCallback: DisplayCallback {
     fn display_separation(
         &mut self,
@@ -0,0 +1,250 @@
+use GS_OK;
+use callback::display::{default_memalloc, default_memfree, DisplayAllocCallback, DisplayCallback, DisplayFormat, DisplayRawDevice,
+                        DisplaySeparationCallback, DisplayUpdateCallback};
+use callback::panic::PanicCallback;
+use callback::poll::PollCallback;
+use callback::stdio::StdioCallback;
+use error::ErrCode;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_void;
+
+bitflags! {
+    // Callbacks, that a GhostscriptHandler actually implements.
+    // Display sub-capabilities only count together with DISPLAY.
+    #[derive(Default)]
+    pub struct HandlerCapabilities: u32 {
+        const DISPLAY = 1 << 0;
+        const DISPLAY_UPDATE = 1 << 1;
+        const DISPLAY_ALLOC = 1 << 2;
+        const DISPLAY_SEPARATION = 1 << 3;
+        const POLL = 1 << 4;
+        const STDIN = 1 << 5;
+        const STDOUT = 1 << 6;
+        const STDERR = 1 << 7;
+    }
+}
+
+// Object safe counterpart of the callback traits, for handlers chosen at runtime.
+// Only the callbacks named in capabilities() are installed, the rest of the methods are never called.
+pub trait GhostscriptHandler {
+    fn capabilities(&self) -> HandlerCapabilities;
+
+    fn on_callback_panic(&mut self, callback_name: &'static str, _error: Box<::std::any::Any + Send + 'static>) -> ErrCode {
+        error!("Panic in ghostscript {} callback, aborting!", callback_name);
+        ::std::process::abort()
+    }
+
+    fn display_open(&mut self, _device: *mut DisplayRawDevice) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_preclose(&mut self, _device: *mut DisplayRawDevice) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_close(&mut self, _device: *mut DisplayRawDevice) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_presize(
+        &mut self,
+        _device: *mut DisplayRawDevice,
+        _width: usize,
+        _height: usize,
+        _raster: usize,
+        _format: DisplayFormat,
+    ) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_size(
+        &mut self,
+        _device: *mut DisplayRawDevice,
+        _width: usize,
+        _height: usize,
+        _raster: usize,
+        _format: DisplayFormat,
+        _pimage: *mut u8,
+    ) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_sync(&mut self, _device: *mut DisplayRawDevice) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_page(&mut self, _device: *mut DisplayRawDevice, _copies: u32, _flush: bool) -> ErrCode {
+        GS_OK
+    }
+
+    fn display_update(&mut self, _device: *mut DisplayRawDevice, _x: usize, _y: usize, _w: usize, _h: usize) -> ErrCode {
+        GS_OK
+    }
+
+    unsafe fn display_memalloc(&mut self, _device: *mut DisplayRawDevice, size: usize) -> *mut c_void {
+        default_memalloc(size)
+    }
+
+    unsafe fn display_memfree(&mut self, _device: *mut DisplayRawDevice, mem: *mut c_void) -> ErrCode {
+        default_memfree(mem)
+    }
+
+    fn display_separation(
+        &mut self,
+        _device: *mut DisplayRawDevice,
+        _component: u32,
+        _component_name: &CStr,
+        _cmyk: (u16, u16, u16, u16),
+    ) -> ErrCode {
+        GS_OK
+    }
+
+    fn poll(&mut self) -> ErrCode {
+        GS_OK
+    }
+
+    fn read_stdin(&mut self, _buf: &mut [u8]) -> Option<usize> {
+        Some(0)
+    }
+
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        buf.len()
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        self.write_stdout(buf)
+    }
+}
+
+// User data for trait object handlers, see GhostscriptBuilder::build_dyn().
+// Every FFI callback is instantiated once for it, whatever the handler behind it.
+pub struct DynHandler(pub Box<GhostscriptHandler>);
+
+impl DynHandler {
+    pub fn new<H: GhostscriptHandler + 'static>(handler: H) -> Self {
+        DynHandler(Box::new(handler))
+    }
+
+    pub fn capabilities(&self) -> HandlerCapabilities {
+        self.0.capabilities()
+    }
+
+    pub fn into_inner(self) -> Box<GhostscriptHandler> {
+        self.0
+    }
+}
+
+impl fmt::Debug for DynHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("DynHandler").field(&self.capabilities()).finish()
+    }
+}
+
+impl PanicCallback for DynHandler {
+    // Safety: only called by the ffi callbacks with the user data pointer given to ghostscript.
+    // It is null or points to a live DynHandler, that nothing else references during the call.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn on_callback_panic(callback_ptr: *mut Self, callback_name: &'static str, error: Box<::std::any::Any + Send + 'static>) -> ErrCode {
+        match unsafe { callback_ptr.as_mut() } {
+            Some(handler) => handler.0.on_callback_panic(callback_name, error),
+            None => {
+                error!("Panic in ghostscript {} callback, aborting!", callback_name);
+                ::std::process::abort()
+            },
+        }
+    }
+}
+
+impl DisplayCallback for DynHandler {
+    fn display_open(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.0.display_open(device)
+    }
+
+    fn display_preclose(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.0.display_preclose(device)
+    }
+
+    fn display_close(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.0.display_close(device)
+    }
+
+    fn display_presize(
+        &mut self,
+        device: *mut DisplayRawDevice,
+        width: usize,
+        height: usize,
+        raster: usize,
+        format: DisplayFormat,
+    ) -> ErrCode {
+        self.0.display_presize(device, width, height, raster, format)
+    }
+
+    fn display_size(
+        &mut self,
+        device: *mut DisplayRawDevice,
+        width: usize,
+        height: usize,
+        raster: usize,
+        format: DisplayFormat,
+        pimage: *mut u8,
+    ) -> ErrCode {
+        self.0.display_size(device, width, height, raster, format, pimage)
+    }
+
+    fn display_sync(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.0.display_sync(device)
+    }
+
+    fn display_page(&mut self, device: *mut DisplayRawDevice, copies: u32, flush: bool) -> ErrCode {
+        self.0.display_page(device, copies, flush)
+    }
+}
+
+impl DisplayUpdateCallback for DynHandler {
+    fn display_update(&mut self, device: *mut DisplayRawDevice, x: usize, y: usize, w: usize, h: usize) -> ErrCode {
+        self.0.display_update(device, x, y, w, h)
+    }
+}
+
+impl DisplayAllocCallback for DynHandler {
+    unsafe fn display_memalloc(&mut self, device: *mut DisplayRawDevice, size: usize) -> *mut c_void {
+        self.0.display_memalloc(device, size)
+    }
+
+    unsafe fn display_memfree(&mut self, device: *mut DisplayRawDevice, mem: *mut c_void) -> ErrCode {
+        self.0.display_memfree(device, mem)
+    }
+}
+
+impl DisplaySeparationCallback for DynHandler {
+    fn display_separation(
+        &mut self,
+        device: *mut DisplayRawDevice,
+        component: u32,
+        component_name: &CStr,
+        cmyk: (u16, u16, u16, u16),
+    ) -> ErrCode {
+        self.0.display_separation(device, component, component_name, cmyk)
+    }
+}
+
+impl PollCallback for DynHandler {
+    fn poll(&mut self) -> ErrCode {
+        self.0.poll()
+    }
+}
+
+impl StdioCallback for DynHandler {
+    fn read_stdin(&mut self, buf: &mut [u8]) -> Option<usize> {
+        self.0.read_stdin(buf)
+    }
+
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        self.0.write_stdout(buf)
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        self.0.write_stderr(buf)
+    }
+}
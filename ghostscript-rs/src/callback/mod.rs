@@ -1,6 +1,8 @@
 extern crate stable_deref_trait;
 
+pub mod closure;
 pub mod display;
+pub mod handler;
 pub mod panic;
 pub mod poll;
 pub mod stdio;
@@ -33,6 +35,9 @@ impl DerefMut for NoCallback {
 
 unsafe impl StableDeref for NoCallback {}
 
+// Lets NoCallback stand for "no data" next to a real callback, e.g. in WithStdio<NoCallback, CaptureStdio>.
+impl panic::PanicCallback for NoCallback {}
+
 impl<T, Q: StableDeref<Target = T> + DerefMut<Target = T>> CallbackSafe for Q {
     type Target = T;
     fn as_stable_mut(&mut self) -> &mut Self::Target {
@@ -1,4 +1,6 @@
-use error::ErrCode;
+use error::{consts, ErrCode};
+
+pub type PanicPayload = Box<::std::any::Any + Send + 'static>;
 
 pub trait PanicCallback {
     fn on_callback_panic(callback_ptr: *mut Self, callback_name: &'static str, _error: Box<::std::any::Any + Send + 'static>) -> ErrCode {
@@ -9,3 +11,33 @@ pub trait PanicCallback {
         ::std::process::abort()
     }
 }
+
+// What happens, when a callback panics during a job of an instance.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PanicMode {
+    // Leave it to PanicCallback::on_callback_panic(), which aborts the process by default.
+    Handler,
+    // Fail the job with a fatal error, and resume the panic with the original payload,
+    // once the gsapi call returns to Rust. The instance is poisoned afterwards.
+    Propagate,
+}
+
+impl Default for PanicMode {
+    fn default() -> Self {
+        PanicMode::Handler
+    }
+}
+
+// Stores the payload in the current job, if it propagates panics. Otherwise gives it back.
+pub(crate) fn catch_job_panic(callback_name: &'static str, error: PanicPayload) -> Option<PanicPayload> {
+    let mut error = Some(error);
+    ::job::with_current_job(|job| job.catch_panic(callback_name, &mut error));
+    error
+}
+
+pub(crate) fn handle_callback_panic<T: PanicCallback>(callback_ptr: *mut T, callback_name: &'static str, error: PanicPayload) -> ErrCode {
+    match catch_job_panic(callback_name, error) {
+        None => consts::FATAL,
+        Some(error) => T::on_callback_panic(callback_ptr, callback_name, error),
+    }
+}
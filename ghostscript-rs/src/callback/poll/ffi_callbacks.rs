@@ -1,15 +1,33 @@
 use super::*;
 use callback::get_cb;
+use callback::panic::handle_callback_panic;
+use error::consts;
 use gs_sys;
 use std::os::raw::c_void;
 use std::panic::catch_unwind;
 
 pub type Poll = gs_sys::ffi::PollCallback;
 
+fn poll_job_control() -> Option<ErrCode> {
+    ::job::poll_current_job().map(|e| {
+        debug!("Interrupting ghostscript job: {}", e);
+        consts::INTERRUPT
+    })
+}
+
 pub unsafe extern "C" fn poll_callback<T: PollCallback>(handle: *mut c_void) -> gs_sys::GsErrorType {
     catch_unwind(|| {
         trace!("poll_callback! Handle: {:p}", handle);
-        get_cb::<T>(handle).poll()
-    }).unwrap_or_else(|e| T::on_callback_panic(handle as *mut T, "poll_callback", e))
+        poll_job_control().unwrap_or_else(|| get_cb::<T>(handle).poll())
+    }).unwrap_or_else(|e| handle_callback_panic(handle as *mut T, "poll_callback", e))
+        .raw_err()
+}
+
+// Installed when user data doesn't poll itself, so that cancellation and deadlines still work.
+pub unsafe extern "C" fn job_control_poll_callback(handle: *mut c_void) -> gs_sys::GsErrorType {
+    catch_unwind(|| {
+        trace!("job_control_poll_callback! Handle: {:p}", handle);
+        poll_job_control().unwrap_or(GS_OK)
+    }).unwrap_or(consts::FATAL)
         .raw_err()
 }
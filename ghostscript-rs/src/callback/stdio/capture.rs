@@ -0,0 +1,133 @@
+use callback::panic::PanicCallback;
+use callback::stdio::StdioCallback;
+
+pub const TRUNCATION_MARKER: &[u8] = b"\n[... output truncated ...]\n";
+
+// Limit of default() buffers. Pass the limit to new(), when this isn't enough.
+pub const DEFAULT_CAPTURE_LIMIT: usize = 1 << 20;
+
+// Output buffer, that keeps at most `limit` bytes, and marks, where the rest was dropped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BoundedBuffer {
+    data: Vec<u8>,
+    limit: usize,
+    truncated: bool,
+}
+
+impl BoundedBuffer {
+    pub fn new(limit: usize) -> Self {
+        BoundedBuffer {
+            data: Vec::new(),
+            limit,
+            truncated: false,
+        }
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        if self.truncated {
+            return;
+        }
+        let room = self.limit - self.data.len();
+        if buf.len() <= room {
+            self.data.extend_from_slice(buf);
+        } else {
+            self.data.extend_from_slice(&buf[..room]);
+            self.data.extend_from_slice(TRUNCATION_MARKER);
+            self.truncated = true;
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    // Empties the buffer for reuse, keeping the limit.
+    pub fn take(&mut self) -> Vec<u8> {
+        self.truncated = false;
+        ::std::mem::replace(&mut self.data, Vec::new())
+    }
+}
+
+impl Default for BoundedBuffer {
+    fn default() -> Self {
+        BoundedBuffer::new(DEFAULT_CAPTURE_LIMIT)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CaptureStdio {
+    pub stdout: BoundedBuffer,
+    pub stderr: BoundedBuffer,
+}
+
+impl CaptureStdio {
+    // Limit applies to each of stdout and stderr separately.
+    pub fn new(limit: usize) -> Self {
+        CaptureStdio {
+            stdout: BoundedBuffer::new(limit),
+            stderr: BoundedBuffer::new(limit),
+        }
+    }
+
+    pub fn into_parts(self) -> (Vec<u8>, Vec<u8>) {
+        (self.stdout.into_inner(), self.stderr.into_inner())
+    }
+
+    pub fn take(&mut self) -> (Vec<u8>, Vec<u8>) {
+        (self.stdout.take(), self.stderr.take())
+    }
+}
+
+impl Default for CaptureStdio {
+    fn default() -> Self {
+        CaptureStdio::new(DEFAULT_CAPTURE_LIMIT)
+    }
+}
+
+impl PanicCallback for CaptureStdio {}
+
+impl StdioCallback for CaptureStdio {
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        self.stdout.write(buf);
+        buf.len()
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        self.stderr.write(buf);
+        buf.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_buffer_truncates_once() {
+        let mut buf = BoundedBuffer::new(5);
+        buf.write(b"abc");
+        buf.write(b"defg");
+        buf.write(b"hij");
+        assert!(buf.is_truncated());
+        let mut expected = b"abcde".to_vec();
+        expected.extend_from_slice(TRUNCATION_MARKER);
+        assert_eq!(buf.as_bytes(), &expected[..]);
+    }
+
+    #[test]
+    fn default_capture_keeps_output() {
+        let mut capture = CaptureStdio::default();
+        capture.write_stdout(b"out");
+        capture.write_stderr(b"err");
+        assert_eq!(capture.take(), (b"out".to_vec(), b"err".to_vec()));
+        assert_eq!(BoundedBuffer::default(), BoundedBuffer::new(DEFAULT_CAPTURE_LIMIT));
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
 use callback::get_cb;
+use callback::panic::{catch_job_panic, handle_callback_panic, PanicPayload};
+use error::consts;
 use gs_sys;
 use std::os::raw::{c_char, c_int, c_void};
 use std::panic::catch_unwind;
@@ -15,24 +17,67 @@ pub unsafe extern "C" fn stdin_callback<T: StdioCallback>(handle: *mut c_void, b
         );
         get_cb::<T>(handle).read_stdin(::std::slice::from_raw_parts_mut(buf as *mut u8, len as _))
     }).unwrap_or_else(|e| {
-        T::on_callback_panic(handle as *mut T, "stdin_callback", e);
+        handle_callback_panic(handle as *mut T, "stdin_callback", e);
         None
     })
         .map(|u| u as _)
         .unwrap_or(-1)
 }
 
-pub unsafe extern "C" fn stdout_callback<T: StdioCallback>(handle: *mut c_void, buf: *const c_char, len: c_int) -> c_int {
+// Reads from the reader attached to the builder. Outside of a job, stdin is at EOF.
+pub unsafe extern "C" fn reader_stdin_callback(handle: *mut c_void, buf: *mut c_char, len: c_int) -> c_int {
     catch_unwind(|| {
         debug!(
-            "stdout_callback! Handle: {:p}, Buffer: {:p}, Len: {}",
+            "reader_stdin_callback! Handle: {:p}, Buffer: {:p}, Len: {}",
             handle, buf, len
         );
-        get_cb::<T>(handle).write_stdout(::std::slice::from_raw_parts(buf as *mut u8, len as _)) as c_int
+        let buf = ::std::slice::from_raw_parts_mut(buf as *mut u8, len as _);
+        ::job::with_current_job(|job| job.stdin().map_or(Some(0), |r| r.read(buf))).unwrap_or(Some(0))
     }).unwrap_or_else(|e| {
-        T::on_callback_panic(handle as *mut T, "stdout_callback", e);
-        0
+        if catch_job_panic("reader_stdin_callback", e).is_some() {
+            error!("Panic in ghostscript stdin reader ({:p})", handle);
+        }
+        None
     })
+        .map(|u| u as _)
+        .unwrap_or(-1)
+}
+
+// Output beyond the limit of the running job is silently discarded.
+fn limit_output(buf: &[u8]) -> &[u8] {
+    let allowed = ::job::with_current_job(|job| job.limits().count_output(buf.len())).unwrap_or(buf.len());
+    &buf[..allowed]
+}
+
+// A job, that propagates the panic, fails with a fatal error. Otherwise the handler decides,
+// and the output callback reports 0 bytes written, as it did before panics could be propagated.
+fn output_panic<T: StdioCallback>(handle: *mut T, callback_name: &'static str, error: PanicPayload) -> c_int {
+    match catch_job_panic(callback_name, error) {
+        None => consts::FATAL.raw_err(),
+        Some(error) => {
+            T::on_callback_panic(handle, callback_name, error);
+            0
+        },
+    }
+}
+
+pub unsafe extern "C" fn stdout_callback<T: StdioCallback>(handle: *mut c_void, buf: *const c_char, len: c_int) -> c_int {
+    catch_unwind(|| {
+        debug!(
+            "stdout_callback! Handle: {:p}, Buffer: {:p}, Len: {}",
+            handle, buf, len
+        );
+        let buf = ::std::slice::from_raw_parts(buf as *mut u8, len as _);
+        match limit_output(buf) {
+            allowed if allowed.len() < buf.len() => {
+                if !allowed.is_empty() {
+                    get_cb::<T>(handle).write_stdout(allowed);
+                }
+                len
+            },
+            allowed => get_cb::<T>(handle).write_stdout(allowed) as c_int,
+        }
+    }).unwrap_or_else(|e| output_panic(handle as *mut T, "stdout_callback", e))
 }
 
 pub unsafe extern "C" fn stderr_callback<T: StdioCallback>(handle: *mut c_void, buf: *const c_char, len: c_int) -> c_int {
@@ -41,9 +86,57 @@ pub unsafe extern "C" fn stderr_callback<T: StdioCallback>(handle: *mut c_void,
             "stderr_callback! Handle: {:p}, Buffer: {:p}, Len: {}",
             handle, buf, len
         );
-        get_cb::<T>(handle).write_stderr(::std::slice::from_raw_parts(buf as *mut u8, len as _)) as c_int
-    }).unwrap_or_else(|e| {
-        T::on_callback_panic(handle as *mut T, "stderr_callback", e);
-        0
-    })
+        let buf = ::std::slice::from_raw_parts(buf as *mut u8, len as _);
+        match limit_output(buf) {
+            allowed if allowed.len() < buf.len() => {
+                if !allowed.is_empty() {
+                    get_cb::<T>(handle).write_stderr(allowed);
+                }
+                len
+            },
+            allowed => get_cb::<T>(handle).write_stderr(allowed) as c_int,
+        }
+    }).unwrap_or_else(|e| output_panic(handle as *mut T, "stderr_callback", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use callback::panic::{PanicCallback, PanicMode};
+    use job::JobControl;
+    use error::ErrCode;
+
+    struct PanickingStdout(u32);
+
+    impl PanicCallback for PanickingStdout {
+        fn on_callback_panic(callback_ptr: *mut Self, _callback_name: &'static str, _error: PanicPayload) -> ErrCode {
+            unsafe { (*callback_ptr).0 += 1 };
+            consts::IO_ERROR
+        }
+    }
+
+    impl StdioCallback for PanickingStdout {
+        fn write_stdout(&mut self, _buf: &[u8]) -> usize {
+            panic!("stdout");
+        }
+    }
+
+    fn write_stdout_in(panic_mode: PanicMode, handler: &mut PanickingStdout) -> c_int {
+        let mut job_control = JobControl::default();
+        job_control.panic_mode = panic_mode;
+        let job = job_control.start();
+        let _guard = job.enter();
+        let buf = b"page 1\n";
+        let handle = handler as *mut PanickingStdout as *mut c_void;
+        unsafe { stdout_callback::<PanickingStdout>(handle, buf.as_ptr() as *const c_char, buf.len() as c_int) }
+    }
+
+    #[test]
+    fn output_panic_returns_zero_to_handler_and_fatal_when_propagated() {
+        let mut handler = PanickingStdout(0);
+        assert_eq!(write_stdout_in(PanicMode::Handler, &mut handler), 0);
+        assert_eq!(handler.0, 1);
+        assert_eq!(write_stdout_in(PanicMode::Propagate, &mut handler), consts::FATAL.raw_err());
+        assert_eq!(handler.0, 1);
+    }
 }
@@ -0,0 +1,145 @@
+use callback::panic::PanicCallback;
+use callback::stdio::StdioCallback;
+use log::Level;
+
+// Lines longer than this are logged in pieces, so that output without newlines can't grow the buffer.
+const MAX_LINE_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Default)]
+struct LineBuffer(Vec<u8>);
+
+impl LineBuffer {
+    fn write<F: FnMut(&[u8])>(&mut self, buf: &[u8], mut emit: F) {
+        for &b in buf {
+            if b == b'\n' {
+                emit(&self.0);
+                self.0.clear();
+            } else {
+                self.0.push(b);
+                if self.0.len() >= MAX_LINE_LEN {
+                    emit(&self.0);
+                    self.0.clear();
+                }
+            }
+        }
+    }
+
+    fn flush<F: FnMut(&[u8])>(&mut self, mut emit: F) {
+        if !self.0.is_empty() {
+            emit(&self.0);
+            self.0.clear();
+        }
+    }
+}
+
+// Forwards interpreter output to the log crate line by line.
+// Stdout goes to info level, stderr to warn level.
+#[derive(Debug, Clone)]
+pub struct LogStdio {
+    target: String,
+    stdout: LineBuffer,
+    stderr: LineBuffer,
+}
+
+impl LogStdio {
+    pub fn new() -> Self {
+        LogStdio::with_target("ghostscript")
+    }
+
+    pub fn with_target<S: Into<String>>(target: S) -> Self {
+        LogStdio {
+            target: target.into(),
+            stdout: LineBuffer::default(),
+            stderr: LineBuffer::default(),
+        }
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    // Logs incomplete last lines. Done automatically on drop.
+    pub fn flush(&mut self) {
+        let target = &self.target;
+        self.stdout.flush(|line| emit(target, Level::Info, line));
+        self.stderr.flush(|line| emit(target, Level::Warn, line));
+    }
+}
+
+impl Default for LogStdio {
+    fn default() -> Self {
+        LogStdio::new()
+    }
+}
+
+impl Drop for LogStdio {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+fn emit(target: &str, level: Level, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    log!(target: target, level, "{}", line.trim_end_matches('\r'));
+}
+
+impl PanicCallback for LogStdio {}
+
+impl StdioCallback for LogStdio {
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        let target = &self.target;
+        self.stdout.write(buf, |line| emit(target, Level::Info, line));
+        buf.len()
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        let target = &self.target;
+        self.stderr.write(buf, |line| emit(target, Level::Warn, line));
+        buf.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &mut LineBuffer, input: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        for buf in input {
+            buffer.write(buf, |line| lines.push(line.to_vec()));
+        }
+        lines
+    }
+
+    #[test]
+    fn lines_are_emitted_when_complete() {
+        let mut buffer = LineBuffer::default();
+        let emitted = lines(&mut buffer, &[b"first li", b"ne\nsecond\n\nthi", b"rd"]);
+        assert_eq!(emitted, vec![b"first line".to_vec(), b"second".to_vec(), Vec::new()]);
+
+        let mut tail = Vec::new();
+        buffer.flush(|line| tail.push(line.to_vec()));
+        buffer.flush(|line| tail.push(line.to_vec()));
+        assert_eq!(tail, vec![b"third".to_vec()]);
+    }
+
+    #[test]
+    fn long_lines_are_emitted_in_pieces() {
+        let mut buffer = LineBuffer::default();
+        let long = vec![b'x'; MAX_LINE_LEN * 2 + 1];
+        let emitted = lines(&mut buffer, &[&long, b"\n"]);
+        assert_eq!(emitted.iter().map(Vec::len).collect::<Vec<_>>(), vec![MAX_LINE_LEN, MAX_LINE_LEN, 1]);
+    }
+
+    #[test]
+    fn output_is_consumed_whole() {
+        let mut stdio = LogStdio::with_target("ghostscript-test");
+        assert_eq!(stdio.target(), "ghostscript-test");
+        assert_eq!(stdio.write_stdout(b"partial"), 7);
+        assert_eq!(stdio.write_stderr(b"line\r\n"), 6);
+        assert_eq!(stdio.stdout.0, b"partial".to_vec());
+        assert!(stdio.stderr.0.is_empty());
+        stdio.flush();
+        assert!(stdio.stdout.0.is_empty());
+    }
+}
@@ -1,5 +1,16 @@
 pub(crate) mod ffi_callbacks;
+mod capture;
+mod logging;
+pub(crate) mod reader;
+mod tee;
+mod with;
+mod writer;
 
+pub use self::capture::{BoundedBuffer, CaptureStdio, DEFAULT_CAPTURE_LIMIT, TRUNCATION_MARKER};
+pub use self::logging::LogStdio;
+pub use self::tee::Tee;
+pub use self::with::WithStdio;
+pub use self::writer::WriterStdio;
 use callback::panic::PanicCallback;
 
 pub trait StdioCallback: PanicCallback {
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+// Reader attached as interpreter stdin with GhostscriptBuilder::with_stdin_reader().
+// In the builder it waits for the first build(), which moves it into a SharedReader of its own,
+// so the instance doesn't share it with later builds from the builder or its clones.
+// Jobs of that instance share it, and continue where the previous one stopped.
+#[derive(Clone)]
+pub(crate) struct SharedReader(Arc<Mutex<Option<Box<Read + Send>>>>);
+
+impl SharedReader {
+    pub(crate) fn new<R: Read + Send + 'static>(reader: R) -> Self {
+        SharedReader(Arc::new(Mutex::new(Some(Box::new(reader)))))
+    }
+
+    // Moves the reader out of the builder. Builds after the first one get stdin at EOF.
+    pub(crate) fn take(&self) -> Option<SharedReader> {
+        match self.0.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            Some(reader) => Some(SharedReader(Arc::new(Mutex::new(Some(reader))))),
+            None => {
+                warn!("Ghostscript stdin reader was taken by an earlier build, stdin is at EOF");
+                None
+            },
+        }
+    }
+
+    // Same convention as StdioCallback::read_stdin(): Some(0) on EOF, None on error.
+    pub(crate) fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut reader = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let reader = match *reader {
+            Some(ref mut reader) => reader,
+            None => return Some(0),
+        };
+        loop {
+            match reader.read(buf) {
+                Ok(len) => return Some(len),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!("Failed to read ghostscript stdin: {}", e);
+                    return None;
+                },
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SharedReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedReader({:p})", &*self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use callback::stdio::ffi_callbacks::reader_stdin_callback;
+    use job::JobControl;
+    use std::os::raw::c_char;
+
+    // Returns a short read of at most 3 bytes, then fails once with Interrupted, then with the error.
+    struct FlakyReader {
+        data: &'static [u8],
+        interrupted: bool,
+        error: Option<io::ErrorKind>,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.is_empty() {
+                if !self.interrupted {
+                    self.interrupted = true;
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                return match self.error {
+                    Some(kind) => Err(kind.into()),
+                    None => Ok(0),
+                };
+            }
+            let len = ::std::cmp::min(::std::cmp::min(buf.len(), 3), self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn read_all(job_control: &JobControl, buf_len: usize) -> Vec<i32> {
+        let job = job_control.start();
+        let _guard = job.enter();
+        let mut buf = vec![0 as c_char; buf_len];
+        (0..4)
+            .map(|_| unsafe { reader_stdin_callback(::std::ptr::null_mut(), buf.as_mut_ptr(), buf_len as _) })
+            .collect()
+    }
+
+    fn job_control_with(reader: FlakyReader) -> JobControl {
+        let mut job_control = JobControl::default();
+        job_control.stdin = SharedReader::new(reader).take();
+        job_control
+    }
+
+    #[test]
+    fn partial_reads_then_eof() {
+        let reader = FlakyReader {
+            data: b"1 2 add",
+            interrupted: false,
+            error: None,
+        };
+        assert_eq!(read_all(&job_control_with(reader), 16), vec![3, 3, 1, 0]);
+    }
+
+    #[test]
+    fn io_error_is_reported_to_ghostscript() {
+        let reader = FlakyReader {
+            data: b"1 2",
+            interrupted: false,
+            error: Some(io::ErrorKind::BrokenPipe),
+        };
+        assert_eq!(read_all(&job_control_with(reader), 16), vec![3, -1, -1, -1]);
+    }
+
+    #[test]
+    fn reader_is_moved_into_the_first_build() {
+        let builder_reader = SharedReader::new(&b"x"[..]);
+        let first = builder_reader.clone().take();
+        assert!(first.is_some());
+        assert!(builder_reader.take().is_none());
+
+        let mut buf = [0u8; 4];
+        assert_eq!(first.as_ref().and_then(|r| r.read(&mut buf)), Some(1));
+        // No reader and no job are both EOF.
+        assert_eq!(read_all(&JobControl::default(), 4), vec![0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,69 @@
+use callback::panic::PanicCallback;
+use callback::stdio::StdioCallback;
+
+// Sends output to both sinks. Nest Tee to get more of them.
+// Stdin is read from the first one only.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<A, B> PanicCallback for Tee<A, B> {}
+
+impl<A: StdioCallback, B: StdioCallback> StdioCallback for Tee<A, B> {
+    fn read_stdin(&mut self, buf: &mut [u8]) -> Option<usize> {
+        self.0.read_stdin(buf)
+    }
+
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        self.0.write_stdout(buf);
+        self.1.write_stdout(buf);
+        buf.len()
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        self.0.write_stderr(buf);
+        self.1.write_stderr(buf);
+        buf.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use callback::stdio::CaptureStdio;
+
+    #[derive(Debug, Default)]
+    struct Input(&'static [u8]);
+
+    impl PanicCallback for Input {}
+
+    impl StdioCallback for Input {
+        fn read_stdin(&mut self, buf: &mut [u8]) -> Option<usize> {
+            let len = ::std::cmp::min(buf.len(), self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Some(len)
+        }
+    }
+
+    #[test]
+    fn output_goes_to_every_sink() {
+        let mut tee = Tee(CaptureStdio::default(), Tee(CaptureStdio::default(), CaptureStdio::new(2)));
+        assert_eq!(tee.write_stdout(b"out"), 3);
+        assert_eq!(tee.write_stderr(b"err"), 3);
+
+        assert_eq!(tee.0.take(), (b"out".to_vec(), b"err".to_vec()));
+        assert_eq!((tee.1).0.take(), (b"out".to_vec(), b"err".to_vec()));
+        // A sink, that takes less, doesn't affect the others.
+        assert!((tee.1).1.stdout.is_truncated());
+    }
+
+    #[test]
+    fn stdin_is_read_from_the_first_sink() {
+        let mut tee = Tee(Input(b"first"), Input(b"second"));
+        let mut buf = [0u8; 16];
+        assert_eq!(tee.read_stdin(&mut buf), Some(5));
+        assert_eq!(&buf[..5], b"first");
+        assert_eq!(tee.read_stdin(&mut buf), Some(0));
+        assert_eq!((tee.1).0, b"second");
+    }
+}
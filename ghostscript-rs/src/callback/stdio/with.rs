@@ -0,0 +1,182 @@
+use callback::display::{DisplayAllocCallback, DisplayCallback, DisplayFormat, DisplayRawDevice, DisplaySeparationCallback,
+                        DisplayUpdateCallback};
+use callback::panic::PanicCallback;
+use callback::poll::PollCallback;
+use callback::stdio::StdioCallback;
+use error::ErrCode;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+
+// Combines user data, that implements display (and/or poll) callbacks,
+// with a separate stdio callback implementation, e.g. CaptureStdio or LogStdio.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WithStdio<D, S> {
+    pub data: D,
+    pub stdio: S,
+}
+
+impl<D, S> WithStdio<D, S> {
+    pub fn new(data: D, stdio: S) -> Self {
+        WithStdio { data, stdio }
+    }
+
+    pub fn into_parts(self) -> (D, S) {
+        (self.data, self.stdio)
+    }
+}
+
+impl<D: PanicCallback, S> PanicCallback for WithStdio<D, S> {
+    // Safety: only called by the ffi callbacks with the user data pointer given to ghostscript.
+    // It is null or points to a live WithStdio, that nothing else references during the call.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn on_callback_panic(callback_ptr: *mut Self, callback_name: &'static str, error: Box<::std::any::Any + Send + 'static>) -> ErrCode {
+        let data_ptr = if callback_ptr.is_null() {
+            ::std::ptr::null_mut()
+        } else {
+            unsafe { &mut (*callback_ptr).data as *mut D }
+        };
+        D::on_callback_panic(data_ptr, callback_name, error)
+    }
+}
+
+impl<D: PanicCallback, S: StdioCallback> StdioCallback for WithStdio<D, S> {
+    fn read_stdin(&mut self, buf: &mut [u8]) -> Option<usize> {
+        self.stdio.read_stdin(buf)
+    }
+
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        self.stdio.write_stdout(buf)
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        self.stdio.write_stderr(buf)
+    }
+}
+
+impl<D: PollCallback, S> PollCallback for WithStdio<D, S> {
+    fn poll(&mut self) -> ErrCode {
+        self.data.poll()
+    }
+}
+
+impl<D: DisplayCallback, S> DisplayCallback for WithStdio<D, S> {
+    fn display_open(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.data.display_open(device)
+    }
+
+    fn display_preclose(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.data.display_preclose(device)
+    }
+
+    fn display_close(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.data.display_close(device)
+    }
+
+    fn display_presize(
+        &mut self,
+        device: *mut DisplayRawDevice,
+        width: usize,
+        height: usize,
+        raster: usize,
+        format: DisplayFormat,
+    ) -> ErrCode {
+        self.data.display_presize(device, width, height, raster, format)
+    }
+
+    fn display_size(
+        &mut self,
+        device: *mut DisplayRawDevice,
+        width: usize,
+        height: usize,
+        raster: usize,
+        format: DisplayFormat,
+        pimage: *mut u8,
+    ) -> ErrCode {
+        self.data.display_size(device, width, height, raster, format, pimage)
+    }
+
+    fn display_sync(&mut self, device: *mut DisplayRawDevice) -> ErrCode {
+        self.data.display_sync(device)
+    }
+
+    fn display_page(&mut self, device: *mut DisplayRawDevice, copies: u32, flush: bool) -> ErrCode {
+        self.data.display_page(device, copies, flush)
+    }
+}
+
+impl<D: DisplayUpdateCallback, S> DisplayUpdateCallback for WithStdio<D, S> {
+    fn display_update(&mut self, device: *mut DisplayRawDevice, x: usize, y: usize, w: usize, h: usize) -> ErrCode {
+        self.data.display_update(device, x, y, w, h)
+    }
+}
+
+impl<D: DisplayAllocCallback, S> DisplayAllocCallback for WithStdio<D, S> {
+    unsafe fn display_memalloc(&mut self, device: *mut DisplayRawDevice, size: usize) -> *mut c_void {
+        self.data.display_memalloc(device, size)
+    }
+
+    unsafe fn display_memfree(&mut self, device: *mut DisplayRawDevice, mem: *mut c_void) -> ErrCode {
+        self.data.display_memfree(device, mem)
+    }
+}
+
+impl<D: DisplaySeparationCallback, S> DisplaySeparationCallback for WithStdio<D, S> {
+    fn display_separation(
+        &mut self,
+        device: *mut DisplayRawDevice,
+        component: u32,
+        component_name: &CStr,
+        cmyk: (u16, u16, u16, u16),
+    ) -> ErrCode {
+        self.data.display_separation(device, component, component_name, cmyk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use callback::stdio::CaptureStdio;
+    use error::consts;
+
+    #[derive(Debug)]
+    struct Data(ErrCode);
+
+    impl PanicCallback for Data {
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        fn on_callback_panic(callback_ptr: *mut Self, _: &'static str, _: Box<::std::any::Any + Send + 'static>) -> ErrCode {
+            if callback_ptr.is_null() {
+                consts::FATAL
+            } else {
+                unsafe { (*callback_ptr).0 }
+            }
+        }
+    }
+
+    impl PollCallback for Data {
+        fn poll(&mut self) -> ErrCode {
+            self.0
+        }
+    }
+
+    #[test]
+    fn stdio_and_data_callbacks_go_to_their_parts() {
+        let mut with = WithStdio::new(Data(consts::INTERRUPT), CaptureStdio::default());
+        assert_eq!(with.write_stdout(b"out"), 3);
+        assert_eq!(with.write_stderr(b"err"), 3);
+        assert_eq!(with.read_stdin(&mut [0u8; 4]), Some(0));
+        assert_eq!(with.poll(), consts::INTERRUPT);
+
+        let (data, mut stdio) = with.into_parts();
+        assert_eq!(data.0, consts::INTERRUPT);
+        assert_eq!(stdio.take(), (b"out".to_vec(), b"err".to_vec()));
+    }
+
+    #[test]
+    fn panic_handler_gets_the_data_part() {
+        let mut with = WithStdio::new(Data(consts::UNDEFINED), ());
+        let handled = WithStdio::on_callback_panic(&mut with as *mut _, "poll_callback", Box::new(()));
+        assert_eq!(handled, consts::UNDEFINED);
+        let handled = WithStdio::<Data, ()>::on_callback_panic(::std::ptr::null_mut(), "poll_callback", Box::new(()));
+        assert_eq!(handled, consts::FATAL);
+    }
+}
@@ -0,0 +1,102 @@
+use callback::panic::PanicCallback;
+use callback::stdio::StdioCallback;
+use std::io::{self, Write};
+
+// Writes both stdout and stderr of the interpreter into a single writer.
+// Output is always reported as consumed. The first write error is kept,
+// and further output is discarded, until take_error() returns it.
+#[derive(Debug)]
+pub struct WriterStdio<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> WriterStdio<W> {
+    pub fn new(writer: W) -> Self {
+        WriterStdio { writer, error: None }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> usize {
+        if self.error.is_none() {
+            if let Err(e) = self.writer.write_all(buf) {
+                error!("Failed to write ghostscript output: {}", e);
+                self.error = Some(e);
+            }
+        }
+        buf.len()
+    }
+}
+
+impl<W: Write> PanicCallback for WriterStdio<W> {}
+
+impl<W: Write> StdioCallback for WriterStdio<W> {
+    fn write_stdout(&mut self, buf: &[u8]) -> usize {
+        self.write_all(buf)
+    }
+
+    fn write_stderr(&mut self, buf: &[u8]) -> usize {
+        self.write_all(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fails the write with the given index, takes everything else.
+    struct FailingWriter {
+        written: Vec<u8>,
+        writes: usize,
+        fail_at: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            if self.writes == self.fail_at {
+                return Err(io::Error::new(io::ErrorKind::Other, "full"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_is_discarded_until_the_error_is_taken() {
+        let mut stdio = WriterStdio::new(FailingWriter {
+            written: Vec::new(),
+            writes: 0,
+            fail_at: 2,
+        });
+        assert_eq!(stdio.write_stdout(b"one "), 4);
+        assert_eq!(stdio.write_stderr(b"two "), 4);
+        assert_eq!(stdio.write_stdout(b"three "), 6);
+        assert_eq!(stdio.get_ref().written, b"one ".to_vec());
+        assert_eq!(stdio.get_ref().writes, 2);
+
+        assert_eq!(stdio.take_error().map(|e| e.kind()), Some(io::ErrorKind::Other));
+        assert!(stdio.take_error().is_none());
+        stdio.write_stdout(b"four");
+        assert_eq!(stdio.into_inner().written, b"one four".to_vec());
+    }
+}
@@ -0,0 +1,110 @@
+// Cancellation tokens and deadlines for interpreter jobs.
+//
+// They are set on a builder or an instance, and checked by the running job, see job module.
+// When one triggers, the job fails with GsError::Cancelled or GsError::TimedOut.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    // Cancelling the parent cancels this token too, but not the other way around.
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn child(&self) -> Self {
+        CancellationToken {
+            cancelled: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    // Same token, that is also cancelled together with the parent.
+    #[cfg(any(feature = "async", test))]
+    pub(crate) fn linked_to(&self, parent: &CancellationToken) -> Self {
+        CancellationToken {
+            cancelled: self.cancelled.clone(),
+            parent: Some(Box::new(parent.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.parent.as_ref().map_or(false, |p| p.is_cancelled())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn at(instant: Instant) -> Self {
+        Deadline(instant)
+    }
+
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(Instant::now() + timeout)
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelling_parent_cancels_child_but_not_the_other_way_around() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let sibling = parent.child();
+
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled() && !sibling.is_cancelled());
+
+        parent.cancel();
+        assert!(sibling.is_cancelled());
+        assert!(sibling.child().is_cancelled());
+    }
+
+    #[test]
+    fn linked_token_shares_the_flag_and_follows_the_parent() {
+        let token = CancellationToken::new();
+        let parent = CancellationToken::new();
+        let linked = token.linked_to(&parent);
+
+        token.cancel();
+        assert!(linked.is_cancelled());
+
+        let token = CancellationToken::new();
+        let linked = token.linked_to(&parent);
+        parent.cancel();
+        assert!(linked.is_cancelled());
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn deadline_passes_at_its_instant() {
+        assert!(Deadline::at(Instant::now()).has_passed());
+        assert!(!Deadline::after(Duration::from_secs(3600)).has_passed());
+        let now = Instant::now();
+        assert_eq!(Deadline::at(now).instant(), now);
+    }
+}
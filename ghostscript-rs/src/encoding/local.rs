@@ -0,0 +1,21 @@
+// Passes OS strings to Ghostscript as they are, without any re-encoding.
+// On Unix file names are arbitrary byte strings, and with LOCAL encoding
+// Ghostscript hands them to fopen() untouched, so even names, that aren't
+// valid UTF-8, can be opened.
+
+use gs_sys;
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Local;
+
+impl ::encoding::StringEncoding for Local {
+    const GHOSTSCRIPT_ENCODING: gs_sys::GsArgEncoding = gs_sys::encoding::LOCAL;
+    type RustType = OsStr;
+    type FfiType = CString;
+
+    fn from_rust_to_ffi<S: AsRef<Self::RustType>>(s: S) -> Self::FfiType {
+        CString::new(s.as_ref().as_bytes()).expect("Init args contain nul characters")
+    }
+}
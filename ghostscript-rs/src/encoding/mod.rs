@@ -1,10 +1,19 @@
 pub mod utf8;
 //pub mod utf16;
+#[cfg(unix)]
+pub mod local;
+
+use std::ffi::CStr;
+use std::fmt;
+
+pub use self::utf8::Utf8;
+#[cfg(unix)]
+pub use self::local::Local;
 
 pub trait StringEncoding {
     const GHOSTSCRIPT_ENCODING: ::gs_sys::GsArgEncoding;
     type RustType: ?Sized;
-    type FfiType;
+    type FfiType: AsRef<CStr> + Clone + fmt::Debug;
 
     fn from_rust_to_ffi<S: AsRef<Self::RustType>>(s: S) -> Self::FfiType;
 }
@@ -42,3 +42,17 @@ pub const VM_RECLAIM: ErrCode = ErrCode(raw_err::VM_RECLAIM);
 pub const NEED_INPUT: ErrCode = ErrCode(raw_err::NEED_INPUT);
 pub const INFO: ErrCode = ErrCode(raw_err::INFO);
 pub const HANDLED: ErrCode = ErrCode(raw_err::HANDLED);
+
+// Not ghostscript codes. Reserved by this crate to report jobs, that were
+// interrupted through cancel::CancellationToken, a deadline or limits::ResourceLimits,
+// and instances, that can't be used after a callback panic was propagated from them.
+pub const CANCELLED: ErrCode = ErrCode(-1001);
+pub const TIMED_OUT: ErrCode = ErrCode(-1002);
+pub const POISONED: ErrCode = ErrCode(-1004);
+// Builds, that gave up waiting for the "synchronized" lock. No job has run then.
+pub const INSTANCE_BUSY: ErrCode = ErrCode(-1008);
+// One code per limits::ResourceLimit, so that GsError::ResourceLimitExceeded says, which one was hit.
+pub const PAGES_LIMIT_EXCEEDED: ErrCode = ErrCode(-1003);
+pub const FRAME_BYTES_LIMIT_EXCEEDED: ErrCode = ErrCode(-1005);
+pub const ALLOCATED_BYTES_LIMIT_EXCEEDED: ErrCode = ErrCode(-1006);
+pub const OUTPUT_BYTES_LIMIT_EXCEEDED: ErrCode = ErrCode(-1007);
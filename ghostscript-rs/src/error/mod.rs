@@ -2,6 +2,7 @@ pub mod consts;
 
 use GS_OK;
 use gs_sys::GsErrorType;
+use limits::ResourceLimit;
 use std::error::Error;
 use std::fmt;
 
@@ -12,6 +13,13 @@ impl ErrCode {
     pub fn raw_err(&self) -> GsErrorType {
         self.0
     }
+
+    pub fn to_result(self) -> Result<(), GsError> {
+        match GsError::from_code(self) {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
 }
 
 impl ::std::default::Default for ErrCode {
@@ -43,6 +51,169 @@ impl Error for ErrCode {
     }
 }
 
+impl From<GsError> for ErrCode {
+    fn from(e: GsError) -> Self {
+        e.code()
+    }
+}
+
+macro_rules! gs_errors {
+    ($($variant:ident => $code:ident,)*) => {
+        #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+        pub enum GsError {
+            $($variant,)*
+            // A job was stopped by limits::ResourceLimits. Each limit has its own code.
+            ResourceLimitExceeded(ResourceLimit),
+            // A code, which isn't in error::consts. Never holds a known code or OK.
+            Unrecognized(GsErrorType),
+        }
+
+        impl GsError {
+            pub fn from_code(code: ErrCode) -> Option<GsError> {
+                match code {
+                    consts::OK => None,
+                    $(consts::$code => Some(GsError::$variant),)*
+                    other => Some(match ResourceLimit::from_code(other) {
+                        Some(limit) => GsError::ResourceLimitExceeded(limit),
+                        None => GsError::Unrecognized(other.0),
+                    }),
+                }
+            }
+
+            pub fn code(&self) -> ErrCode {
+                match *self {
+                    $(GsError::$variant => consts::$code,)*
+                    GsError::ResourceLimitExceeded(limit) => limit.code(),
+                    GsError::Unrecognized(other) => ErrCode(other),
+                }
+            }
+        }
+    };
+}
+
+gs_errors! {
+    UnknownError => UNKNOWN_ERROR,
+    DictFull => DICT_FULL,
+    DictStackOverflow => DICT_STACK_OVERFLOW,
+    DictStackUnderflow => DICT_STACK_UNDERFLOW,
+    ExecStackOverflow => EXEC_STACK_OVERFLOW,
+    Interrupt => INTERRUPT,
+    InvalidAccess => INVALID_ACCESS,
+    InvalidExit => INVALID_EXIT,
+    InvalidFileAccess => INVALID_FILE_ACCESS,
+    InvalidFont => INVALID_FONT,
+    InvalidRestore => INVALID_RESTORE,
+    IoError => IO_ERROR,
+    LimitCheck => LIMIT_CHECK,
+    NoCurrentPoint => NO_CURRENT_POINT,
+    RangeCheck => RANGE_CHECK,
+    StackOverflow => STACK_OVERFLOW,
+    StackUnderflow => STACK_UNDERFLOW,
+    SyntaxError => SYNTAX_ERROR,
+    Timeout => TIMEOUT,
+    TypeCheck => TYPECHECK,
+    Undefined => UNDEFINED,
+    UndefinedFilename => UNDEFINED_FILENAME,
+    UndefinedResult => UNDEFINED_RESULT,
+    UnmatchedMark => UNMATCHED_MARK,
+    VmError => VM_ERROR,
+    ConfigurationError => CONFIGURATION_ERROR,
+    UndefinedResource => UNDEFINED_RESOURCE,
+    Unregistered => UNREGISTERED,
+    InvalidContext => INVALID_CONTEXT,
+    InvalidId => INVALID_ID,
+    HitDetected => HIT_DETECTED,
+    Fatal => FATAL,
+    Quit => QUIT,
+    InterpreterExit => INTERPRETER_EXIT,
+    RemapColor => REMAP_COLOR,
+    ExecStackUnderflow => EXEC_STACK_UNDERFLOW,
+    VmReclaim => VM_RECLAIM,
+    NeedInput => NEED_INPUT,
+    Info => INFO,
+    Handled => HANDLED,
+    Cancelled => CANCELLED,
+    TimedOut => TIMED_OUT,
+    Poisoned => POISONED,
+    InstanceBusy => INSTANCE_BUSY,
+}
+
+impl GsError {
+    pub fn from_raw(code: GsErrorType) -> Option<GsError> {
+        GsError::from_code(ErrCode(code))
+    }
+
+    pub fn raw_err(&self) -> GsErrorType {
+        self.code().raw_err()
+    }
+
+    pub fn name(&self) -> &'static str {
+        error_code_to_str_unwrap(self.code())
+    }
+
+    pub fn is_quit(&self) -> bool {
+        *self == GsError::Quit
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        *self == GsError::Fatal
+    }
+
+    pub fn is_interrupt(&self) -> bool {
+        *self == GsError::Interrupt
+    }
+
+    pub fn is_need_input(&self) -> bool {
+        *self == GsError::NeedInput
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self == GsError::Cancelled
+    }
+
+    pub fn is_timed_out(&self) -> bool {
+        *self == GsError::TimedOut
+    }
+
+    pub fn is_resource_limit_exceeded(&self) -> bool {
+        self.exceeded_limit().is_some()
+    }
+
+    pub fn exceeded_limit(&self) -> Option<ResourceLimit> {
+        match *self {
+            GsError::ResourceLimitExceeded(limit) => Some(limit),
+            _ => None,
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        *self == GsError::Poisoned
+    }
+
+    pub fn is_instance_busy(&self) -> bool {
+        *self == GsError::InstanceBusy
+    }
+
+    // Errors from UNKNOWN_ERROR to INVALID_ID are the ones, that PostScript programs
+    // can see and handle with errordict. The rest are interpreter-internal conditions.
+    pub fn is_postscript_error(&self) -> bool {
+        let code = self.raw_err();
+        code <= consts::UNKNOWN_ERROR.raw_err() && code >= consts::INVALID_ID.raw_err()
+    }
+}
+
+impl fmt::Display for GsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ghostscript error: {} ({})", self.name(), self.raw_err())
+    }
+}
+
+impl Error for GsError {
+    fn description(&self) -> &str {
+        self.name()
+    }
+}
+
 pub(crate) fn error_code_to_str_unwrap(e: ErrCode) -> &'static str {
     error_code_to_str(e).unwrap_or("Unrecognized error code")
 }
@@ -90,6 +261,40 @@ pub fn error_code_to_str(e: ErrCode) -> Option<&'static str> {
         consts::NEED_INPUT => Some("NEED_INPUT"),
         consts::INFO => Some("INFO"),
         consts::HANDLED => Some("HANDLED"),
+        consts::CANCELLED => Some("CANCELLED"),
+        consts::TIMED_OUT => Some("TIMED_OUT"),
+        consts::PAGES_LIMIT_EXCEEDED => Some("PAGES_LIMIT_EXCEEDED"),
+        consts::FRAME_BYTES_LIMIT_EXCEEDED => Some("FRAME_BYTES_LIMIT_EXCEEDED"),
+        consts::ALLOCATED_BYTES_LIMIT_EXCEEDED => Some("ALLOCATED_BYTES_LIMIT_EXCEEDED"),
+        consts::OUTPUT_BYTES_LIMIT_EXCEEDED => Some("OUTPUT_BYTES_LIMIT_EXCEEDED"),
+        consts::POISONED => Some("POISONED"),
+        consts::INSTANCE_BUSY => Some("INSTANCE_BUSY"),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gs_error_roundtrips_known_codes() {
+        for raw in -1010..1 {
+            let code = ErrCode(raw);
+            match GsError::from_code(code) {
+                None => assert_eq!(code, consts::OK),
+                Some(GsError::Unrecognized(c)) => assert!(error_code_to_str(ErrCode(c)).is_none()),
+                Some(e) => {
+                    assert_eq!(e.code(), code);
+                    assert_eq!(e.name(), error_code_to_str_unwrap(code));
+                },
+            }
+        }
+        assert_eq!(
+            GsError::from_code(consts::OUTPUT_BYTES_LIMIT_EXCEEDED).and_then(|e| e.exceeded_limit()),
+            Some(ResourceLimit::OutputBytes)
+        );
+        assert!(GsError::SyntaxError.is_postscript_error());
+        assert!(!GsError::Quit.is_postscript_error());
+    }
+}
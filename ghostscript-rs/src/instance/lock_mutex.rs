@@ -1,13 +1,128 @@
-use std::sync::{Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+// Builds take the lock in the order they came, those that time out leave the queue.
+#[derive(Debug, Default)]
+struct LockState {
+    held: bool,
+    next_ticket: u64,
+    waiting: VecDeque<u64>,
+}
 
 lazy_static! {
-    static ref GHOSTSCRIPT_LOCK: Mutex<()> = {
-         Mutex::new(())
+    static ref GHOSTSCRIPT_LOCK: Mutex<LockState> = {
+         Mutex::new(LockState::default())
     };
+    static ref LOCK_RELEASED: Condvar = Condvar::new();
+}
+
+// Tests, that take the instance lock, take this one first, so that they don't queue behind each other.
+#[cfg(test)]
+lazy_static! {
+    static ref TEST_SERIAL: Mutex<()> = Mutex::new(());
+}
+
+#[cfg(test)]
+pub(crate) fn serialize_test() -> MutexGuard<'static, ()> {
+    TEST_SERIAL.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The state is consistent at every point, where a panic could poison the mutex.
+fn state() -> MutexGuard<'static, LockState> {
+    GHOSTSCRIPT_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[derive(Debug)]
+pub struct InstanceLock(());
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        state().held = false;
+        LOCK_RELEASED.notify_all();
+    }
 }
 
-pub type LockType = MutexGuard<'static, ()>;
+// No guard for libraries, that run instances in parallel.
+pub type LockType = Option<InstanceLock>;
 
 pub fn get_lock() -> LockType {
-    GHOSTSCRIPT_LOCK.lock().unwrap()
+    get_lock_timeout(None).expect("Bug! Waiting for the lock without timeout has timed out")
+}
+
+// None, if the lock couldn't be taken in time.
+// Until the library is probed, the lock is taken first, and given back, if the probe finds it isn't needed.
+pub fn get_lock_timeout(timeout: Option<Duration>) -> Option<LockType> {
+    if super::multi::cached() == Some(true) {
+        return Some(None);
+    }
+    let lock = acquire(timeout)?;
+    if super::multi::probe_locked() {
+        return Some(None);
+    }
+    Some(Some(lock))
+}
+
+pub(crate) fn exclusive() -> InstanceLock {
+    acquire(None).expect("Bug! Waiting for the lock without timeout has timed out")
+}
+
+fn acquire(timeout: Option<Duration>) -> Option<InstanceLock> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut state = state();
+    let ticket = state.next_ticket;
+    state.next_ticket += 1;
+    state.waiting.push_back(ticket);
+
+    loop {
+        if !state.held && state.waiting.front() == Some(&ticket) {
+            state.waiting.pop_front();
+            state.held = true;
+            return Some(InstanceLock(()));
+        }
+        state = match deadline {
+            None => LOCK_RELEASED.wait(state).unwrap_or_else(PoisonError::into_inner),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    state.waiting.retain(|&t| t != ticket);
+                    // The next one in the queue may be first in line now.
+                    LOCK_RELEASED.notify_all();
+                    return None;
+                }
+                LOCK_RELEASED
+                    .wait_timeout(state, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_for_held_lock_times_out() {
+        let _serial = serialize_test();
+        let held = acquire(None).unwrap();
+        assert!(acquire(Some(Duration::from_millis(10))).is_none());
+        ::std::mem::drop(held);
+        assert!(acquire(Some(Duration::from_secs(0))).is_some());
+        assert!(state().waiting.is_empty());
+    }
+
+    #[test]
+    fn unprobed_build_doesnt_wait_past_its_timeout() {
+        let _serial = serialize_test();
+        let held = acquire(None).unwrap();
+        let parallel = super::super::multi::cached() == Some(true);
+        let started = Instant::now();
+        let lock = get_lock_timeout(Some(Duration::from_millis(10)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // Only a library, that is known to run instances in parallel, doesn't need the held lock.
+        assert_eq!(lock.is_some(), parallel);
+        ::std::mem::drop(held);
+    }
 }
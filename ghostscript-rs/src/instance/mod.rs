@@ -1,8 +1,20 @@
+use DefaultEncoding;
+use callback::CallbackSafe;
+use callback::panic::PanicMode;
+use cancel::{CancellationToken, Deadline};
 use device_list::DeviceList;
-use error::ErrCode;
+use error::{ErrCode, GsError};
 use gs_sys;
+use interpreter::UserErrors;
+use job::{ActiveJob, JobControl};
+use limits::ResourceLimits;
+use std::marker::PhantomData;
 use std::ops::Drop;
 use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "synchronized")]
+mod multi;
 
 #[cfg(not(feature = "synchronized"))]
 pub(crate) mod lock_none;
@@ -15,25 +27,90 @@ pub(crate) mod lock_mutex;
 pub(crate) use self::lock_mutex as lock;
 
 #[derive(Debug)]
-pub struct Ghostscript<T> {
+pub struct Ghostscript<T, E = DefaultEncoding> {
     #[allow(unused)] pub(crate) lock: lock::LockType,
 
     pub(crate) instance: *mut gs_sys::GsRawInstance,
     pub(crate) initialized: bool,
+    // Set after a callback panic was propagated out of a job.
+    pub(crate) poisoned: bool,
+    pub(crate) user_errors: UserErrors,
+    pub(crate) job_control: JobControl,
     pub(crate) user_data: Option<T>,
     pub(crate) display_callback: Option<Arc<gs_sys::display::DisplayCallback>>,
+    pub(crate) _encoding: PhantomData<E>,
 }
 
-impl<T> Ghostscript<T> {
+impl<T, E> Ghostscript<T, E> {
     pub fn into_inner(mut self) -> T {
         self.user_data.take().expect("Bug! user_data is missing")
     }
 
+    pub fn user_errors(&self) -> UserErrors {
+        self.user_errors
+    }
+
+    pub fn set_user_errors(&mut self, user_errors: UserErrors) -> &mut Self {
+        self.user_errors = user_errors;
+        self
+    }
+
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) -> &mut Self {
+        self.job_control.token = token;
+        self
+    }
+
+    // Limits the run time of every following interpret_*() call or stream.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.job_control.timeout = timeout;
+        self
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Deadline>) -> &mut Self {
+        self.job_control.deadline = deadline;
+        self
+    }
+
+    pub fn set_resource_limits(&mut self, limits: ResourceLimits) -> &mut Self {
+        self.job_control.limits = limits;
+        self
+    }
+
+    pub fn set_panic_mode(&mut self, panic_mode: PanicMode) -> &mut Self {
+        self.job_control.panic_mode = panic_mode;
+        self
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    pub(crate) fn check_poisoned(&self) -> Result<(), GsError> {
+        if self.poisoned {
+            Err(GsError::Poisoned)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Resumes the panic, that a callback of the finished job has propagated, if any.
+    pub(crate) fn resume_panic(&mut self, job: &ActiveJob) {
+        if let Some(payload) = job.take_panic() {
+            self.poisoned = true;
+            if ::std::thread::panicking() {
+                // E.g. an unclosed stream is being dropped during another panic.
+                debug!("Discarding ghostscript callback panic, the thread is already panicking");
+                return;
+            }
+            ::std::panic::resume_unwind(payload);
+        }
+    }
+
     pub unsafe fn as_raw_instance(&mut self) -> *mut gs_sys::GsRawInstance {
         self.instance
     }
 
-    pub fn get_default_device_list(&self) -> Result<DeviceList, ErrCode> {
+    pub fn get_default_device_list(&self) -> Result<DeviceList, GsError> {
         let mut ptr: *mut u8 = ::std::ptr::null_mut();
         let mut size = 0;
 
@@ -43,9 +120,7 @@ impl<T> Ghostscript<T> {
                 &mut ptr as *mut *mut _ as *mut *mut ::std::os::raw::c_char,
                 &mut size,
             );
-            if err != gs_sys::GS_OK {
-                return Err(ErrCode(err));
-            }
+            ErrCode(err).to_result()?;
         }
 
         let s = unsafe {
@@ -57,7 +132,28 @@ impl<T> Ghostscript<T> {
     }
 }
 
-impl<T> Drop for Ghostscript<T> {
+#[cfg(feature = "synchronized")]
+impl Ghostscript<()> {
+    // Whether the linked library may run several instances at once, so that builds don't
+    // need the "synchronized" lock. Probes the library on the first call, waiting for that lock,
+    // and again on later calls, as long as the library isn't loaded yet.
+    pub fn supports_multiple_instances() -> bool {
+        multi::supports_multiple_instances()
+    }
+}
+
+impl<T: CallbackSafe, E> Ghostscript<T, E> {
+    // Only the stable target is exposed, so the data can't be swapped out from under the callbacks.
+    // No callback can run while it is borrowed, since every gsapi call needs &mut self too.
+    pub fn user_data_mut(&mut self) -> &mut T::Target {
+        self.user_data
+            .as_mut()
+            .expect("Bug! user_data is missing")
+            .as_stable_mut()
+    }
+}
+
+impl<T, E> Drop for Ghostscript<T, E> {
     fn drop(&mut self) {
         if self.initialized {
             unsafe { gs_sys::ffi::gsapi_exit(self.instance) };
@@ -0,0 +1,136 @@
+// Detection of libraries, that allow more than one instance at a time, i.e. built with GS_THREADSAFE.
+//
+// Only the library can tell, so the first probe creates two bare instances, and checks,
+// whether the second one fails. The probe holds the instance lock of "synchronized",
+// so no build creates an instance meanwhile. Builds take that lock first, within their timeout,
+// and probe under it, so a busy lock fails them in time instead of blocking the probe.
+// Without "synchronized" nothing would keep them apart, so the probe isn't available then.
+//
+// The answer is cached for the lifetime of the process. A library, that can't tell, counts
+// as single-instance, except under "dlopen" before it is loaded, when it is asked again later.
+
+use gs_sys;
+use std::os::raw::c_long;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Earlier revisions have global interpreter state, whatever the build options.
+const MIN_REVISION: c_long = 950;
+
+const UNKNOWN: usize = 0;
+const UNSUPPORTED: usize = 1;
+const SUPPORTED: usize = 2;
+
+static PROBED: AtomicUsize = AtomicUsize::new(UNKNOWN);
+
+pub(crate) fn supports_multiple_instances() -> bool {
+    match cached() {
+        Some(supported) => supported,
+        // No need to wait for the lock, the probe couldn't tell yet.
+        None if !library_loaded() => false,
+        None => {
+            let _lock = super::lock::exclusive();
+            probe_locked()
+        },
+    }
+}
+
+// The answer, if some probe already found it.
+pub(crate) fn cached() -> Option<bool> {
+    match PROBED.load(Ordering::SeqCst) {
+        UNKNOWN => None,
+        probed => Some(probed == SUPPORTED),
+    }
+}
+
+fn revision() -> Option<c_long> {
+    let mut revision: gs_sys::revision::GsApiRevision = unsafe { ::std::mem::zeroed() };
+    let err = unsafe {
+        gs_sys::ffi::gsapi_revision(
+            &mut revision,
+            ::std::mem::size_of::<gs_sys::revision::GsApiRevision>() as _,
+        )
+    };
+    if err != 0 {
+        return None;
+    }
+    Some(revision.revision)
+}
+
+#[cfg(feature = "dlopen")]
+fn library_loaded() -> bool {
+    gs_sys::dynamic::is_loaded()
+}
+
+#[cfg(not(feature = "dlopen"))]
+fn library_loaded() -> bool {
+    true
+}
+
+fn cache(supported: bool) -> bool {
+    PROBED.store(if supported { SUPPORTED } else { UNSUPPORTED }, Ordering::SeqCst);
+    supported
+}
+
+// Must be called under the instance lock. Another thread may have probed, while this one waited for it.
+pub(crate) fn probe_locked() -> bool {
+    if let Some(supported) = cached() {
+        return supported;
+    }
+    if !library_loaded() {
+        debug!("Ghostscript library isn't loaded yet, assuming it supports only one instance for now");
+        return false;
+    }
+    match revision() {
+        Some(revision) if revision >= MIN_REVISION => {},
+        Some(revision) => {
+            debug!("Ghostscript revision {} supports only one instance", revision);
+            return cache(false);
+        },
+        None => {
+            debug!("Ghostscript library revision is unknown, assuming it supports only one instance");
+            return cache(false);
+        },
+    }
+    match unsafe { probe_instances() } {
+        Some(supported) => {
+            debug!("Ghostscript library supports multiple instances: {}", supported);
+            cache(supported)
+        },
+        None => {
+            debug!("Ghostscript library failed to create an instance, assuming it supports only one");
+            cache(false)
+        },
+    }
+}
+
+// Must be called under the instance lock.
+unsafe fn probe_instances() -> Option<bool> {
+    let mut first = ::std::ptr::null_mut();
+    let mut second = ::std::ptr::null_mut();
+    if gs_sys::ffi::gsapi_new_instance(&mut first, ::std::ptr::null_mut()) < 0 {
+        debug!("Failed to create the first ghostscript instance for the probe");
+        return None;
+    }
+    // Libraries without GS_THREADSAFE fail with FATAL here.
+    let err = gs_sys::ffi::gsapi_new_instance(&mut second, ::std::ptr::null_mut());
+    if err >= 0 {
+        gs_sys::ffi::gsapi_delete_instance(second);
+    }
+    gs_sys::ffi::gsapi_delete_instance(first);
+    Some(err >= 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "dlopen")]
+    #[test]
+    fn answer_is_not_cached_until_the_library_is_loaded() {
+        let supported = supports_multiple_instances();
+        if !gs_sys::dynamic::is_loaded() {
+            assert!(!supported);
+            assert_eq!(PROBED.load(Ordering::SeqCst), UNKNOWN);
+        }
+    }
+}
@@ -1,35 +1,40 @@
 mod stream;
 
 use super::*;
-use DefaultEncoding as Encoding;
 use gs_sys;
+use std::io;
 
-impl<'a, T> InterpreterStream<'a> for stream::GhostscriptStream<'a, T> {
+impl<'a, T, E> InterpreterStream<'a> for stream::GhostscriptStream<'a, T, E> {
     fn is_completed(&self) -> bool {
         self.is_completed()
     }
 
+    fn error_location(&self) -> Option<InputLocation> {
+        self.error_location()
+    }
+
     fn close(self) -> InterpreterResult {
         self.close()
     }
 }
 
-impl<'a, T: 'a> Interpreter<'a> for ::instance::Ghostscript<T> {
-    type Stream = stream::GhostscriptStream<'a, T>;
+impl<'a, T: 'a, E: StringEncoding + 'a> Interpreter<'a> for ::instance::Ghostscript<T, E> {
+    type Encoding = E;
+    type Stream = stream::GhostscriptStream<'a, T, E>;
 
-    fn open_interpreter_stream(&'a mut self) -> Result<Self::Stream, InterpreterResult> {
+    fn open_interpreter_stream(&'a mut self) -> Result<Self::Stream, InterpreterError> {
         stream::GhostscriptStream::new(self)
     }
 
     fn interpret_buffer(&mut self, mut buffer: &[u8]) -> InterpreterResult {
         let mut os = match self.open_interpreter_stream() {
             Ok(os) => os,
-            Err(r @ InterpreterResult(_, _)) => return r,
+            Err(e) => return e.into(),
         };
 
         if let Err(e) = ::std::io::copy(&mut buffer, &mut os) {
-            if e.kind() != ::std::io::ErrorKind::Other {
-                // Interpreter must error with ErrorKind::Other only, so this
+            if e.get_ref().and_then(|e| e.downcast_ref::<GsError>()).is_none() {
+                // Interpreter must error with GsError payload only, so this
                 // isn't an interpreter error. Something inside copy() must have failed.
                 // For byte buffer copying there should be no such place, but let's panic just in case.
                 panic!("Bug! Unexpected kind of error on copying to interpreter stream!");
@@ -39,17 +44,58 @@ impl<'a, T: 'a> Interpreter<'a> for ::instance::Ghostscript<T> {
         os.close()
     }
 
-    fn interpret_file(&mut self, file_name: &<Encoding as StringEncoding>::RustType) -> InterpreterResult {
-        let file_name = Encoding::from_rust_to_ffi(file_name);
+    fn interpret_reader<R: Read>(&mut self, reader: R, mut opts: ReaderOptions) -> io::Result<ReaderResult> {
+        let mut os = match self.open_interpreter_stream() {
+            Ok(os) => os,
+            Err(e) => {
+                return Ok(ReaderResult {
+                    result: e.into(),
+                    bytes_accepted: 0,
+                    cancelled: false,
+                    location: None,
+                })
+            },
+        };
+
+        let (bytes_accepted, cancelled) = match feed_reader(&mut os, reader, &mut opts, stream::MAX_BYTES_PER_WRITE) {
+            Ok(fed) => fed,
+            Err(e) => {
+                let result = os.close();
+                debug!("Reader failed. Close result: ({:?})", result);
+                return Err(e);
+            },
+        };
+
+        let (result, location) = os.close_with_location();
+        Ok(ReaderResult {
+            result,
+            bytes_accepted,
+            cancelled,
+            location,
+        })
+    }
+
+    fn interpret_file<S>(&mut self, file_name: &S) -> InterpreterResult
+    where
+        S: AsRef<E::RustType> + ?Sized,
+    {
+        if let Err(e) = self.check_poisoned() {
+            return InterpreterResult(e.code(), PostscriptExit::default());
+        }
+        let file_name = E::from_rust_to_ffi(file_name);
         let mut pexit_code: PostscriptExitCode = 0;
+        let user_errors = self.user_errors.raw();
+        let job = self.job_control.start();
+        let _guard = job.enter();
         let err = unsafe {
             gs_sys::ffi::gsapi_run_file(
                 self.as_raw_instance(),
-                file_name.as_ptr(),
-                0,
+                file_name.as_ref().as_ptr(),
+                user_errors,
                 &mut pexit_code,
             )
         };
-        InterpreterResult(ErrCode(err), pexit_code)
+        self.resume_panic(&job);
+        job.map_result(InterpreterResult::from_raw(err, pexit_code))
     }
 }
@@ -1,46 +1,93 @@
-use error::ErrCode;
+use job::ActiveJob;
+use error::{consts, GsError};
 use gs_sys;
 use instance::Ghostscript;
-use interpreter::InterpreterResult;
-use interpreter::PostscriptExitCode;
+use interpreter::{InputLocation, InterpreterError, InterpreterResult};
+use interpreter::{PostscriptExit, PostscriptExitCode};
 use std::os::raw::c_char;
 
 // This is the limit of run_string() calls.
 // Data chunk delivered per call will be at most this size.
-const MAX_BYTES_PER_WRITE: usize = 65_535;
+pub(super) const MAX_BYTES_PER_WRITE: usize = 65_535;
 
 #[derive(Debug)]
-enum State<'a, T: 'a> {
-    Running(&'a mut Ghostscript<T>),
+enum State<'a, T: 'a, E: 'a> {
+    Running(&'a mut Ghostscript<T, E>),
     Completed(InterpreterResult),
     Closed,
 }
 
+// Position of the next chunk in the whole program, that has been fed so far.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct InputPosition {
+    offset: u64,
+    line: u64,
+}
+
+impl InputPosition {
+    fn new() -> Self {
+        InputPosition { offset: 0, line: 1 }
+    }
+
+    // Location of the chunk, that is fed next.
+    fn advance(&mut self, chunk: &[u8]) -> InputLocation {
+        let location = InputLocation {
+            offset: self.offset,
+            length: chunk.len(),
+            line: self.line,
+        };
+        self.offset += chunk.len() as u64;
+        self.line += chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+        location
+    }
+
+    // Failures at the end of input, e.g. an unterminated procedure.
+    fn end(&self) -> InputLocation {
+        InputLocation {
+            offset: self.offset,
+            length: 0,
+            line: self.line,
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct GhostscriptStream<'a, T: 'a>(State<'a, T>);
+pub struct GhostscriptStream<'a, T: 'a, E: 'a> {
+    state: State<'a, T, E>,
+    position: InputPosition,
+    error_location: Option<InputLocation>,
+    // Timeouts count from the moment the stream is opened.
+    job: ActiveJob,
+}
 
-impl<'a, T: 'a> ::std::io::Write for GhostscriptStream<'a, T> {
+impl<'a, T: 'a, E: 'a> ::std::io::Write for GhostscriptStream<'a, T, E> {
     fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
         let write_len = ::std::cmp::min(buf.len(), MAX_BYTES_PER_WRITE);
 
-        let completed: InterpreterResult = match self.0 {
+        let chunk = &buf[..write_len];
+        let completed: InterpreterResult = match self.state {
             State::Running(ref mut instance) => {
                 let mut pexit_code: PostscriptExitCode = 0;
+                let _guard = self.job.enter();
                 let err = unsafe {
                     gs_sys::ffi::gsapi_run_string_continue(
                         instance.as_raw_instance(),
-                        buf.as_ptr() as *const c_char,
-                        write_len as _,
-                        0,
+                        chunk.as_ptr() as *const c_char,
+                        chunk.len() as _,
+                        instance.user_errors.raw(),
                         &mut pexit_code,
                     )
                 };
+                instance.resume_panic(&self.job);
+
+                let chunk_location = self.position.advance(chunk);
 
                 if err == gs_sys::error::NEED_INPUT {
                     return Ok(write_len);
                 }
 
-                InterpreterResult(ErrCode(err), pexit_code)
+                let result = self.job.map_result(InterpreterResult::from_raw(err, pexit_code));
+                self.record_error_location(result, chunk_location)
             },
             State::Completed(interpreter_result) => {
                 // Silently ignore the rest of the program or error-out, depending on ErrCode.
@@ -49,14 +96,9 @@ impl<'a, T: 'a> ::std::io::Write for GhostscriptStream<'a, T> {
             State::Closed => unreachable!("Bug! This variant should never be visible outside of drop/close!"),
         };
 
-        self.0 = State::Completed(completed);
-        match completed {
-            InterpreterResult(::error::consts::QUIT, _) => Ok(write_len),
-            InterpreterResult(other, _) => Err(::std::io::Error::new(
-                ::std::io::ErrorKind::Other,
-                ::error::error_code_to_str_unwrap(other),
-            )),
-        }
+        self.state = State::Completed(completed);
+        completed.into_write_result()?;
+        Ok(write_len)
     }
 
     fn flush(&mut self) -> ::std::io::Result<()> {
@@ -64,45 +106,95 @@ impl<'a, T: 'a> ::std::io::Write for GhostscriptStream<'a, T> {
     }
 }
 
-impl<'a, T> GhostscriptStream<'a, T> {
-    pub(crate) fn new(instance: &'a mut Ghostscript<T>) -> Result<Self, InterpreterResult> {
+impl<'a, T, E> GhostscriptStream<'a, T, E> {
+    pub(crate) fn new(instance: &'a mut Ghostscript<T, E>) -> Result<Self, InterpreterError> {
+        if let Err(error) = instance.check_poisoned() {
+            return Err(InterpreterError {
+                error,
+                exit_code: PostscriptExit::default(),
+                location: None,
+            });
+        }
+        let job = instance.job_control.start();
         let mut pexit_code: PostscriptExitCode = 0;
-        let err = unsafe { gs_sys::ffi::gsapi_run_string_begin(instance.as_raw_instance(), 0, &mut pexit_code) };
-        if err != gs_sys::GS_OK {
-            return Err(InterpreterResult(ErrCode(err), pexit_code));
+        let err = {
+            let _guard = job.enter();
+            let user_errors = instance.user_errors.raw();
+            unsafe { gs_sys::ffi::gsapi_run_string_begin(instance.as_raw_instance(), user_errors, &mut pexit_code) }
+        };
+        instance.resume_panic(&job);
+        job.map_result(InterpreterResult::from_raw(err, pexit_code)).into_result()?;
+        Ok(GhostscriptStream::from_state(State::Running(instance), job))
+    }
+
+    fn from_state(state: State<'a, T, E>, job: ActiveJob) -> Self {
+        GhostscriptStream {
+            state,
+            position: InputPosition::new(),
+            error_location: None,
+            job,
         }
-        Ok(GhostscriptStream(State::Running(instance)))
+    }
+
+    // Remembers the location of the chunk, that was being fed, when the interpreter failed.
+    fn record_error_location(&mut self, result: InterpreterResult, location: InputLocation) -> InterpreterResult {
+        match result.0.to_result() {
+            Ok(()) | Err(GsError::Quit) => {},
+            Err(_) => self.error_location = Some(location),
+        }
+        result
+    }
+
+    // Location of the chunk, that was being fed, when the interpreter failed.
+    pub fn error_location(&self) -> Option<InputLocation> {
+        self.error_location
     }
 
     pub fn is_completed(&self) -> bool {
-        match self.0 {
+        match self.state {
             State::Running(_) => false,
             State::Completed(_) => true,
             State::Closed => unreachable!("Bug! This variant should never be visible outside of drop/close!"),
         }
     }
 
-    pub fn close(mut self) -> InterpreterResult {
-        match ::std::mem::replace(&mut self.0, State::Closed) {
+    pub fn close(self) -> InterpreterResult {
+        self.close_with_location().0
+    }
+
+    pub fn close_with_location(mut self) -> (InterpreterResult, Option<InputLocation>) {
+        let result = match ::std::mem::replace(&mut self.state, State::Closed) {
+            // A callback panic was resumed out of write(), the instance can't end the program.
+            State::Running(ref instance) if instance.is_poisoned() => {
+                InterpreterResult(consts::POISONED, PostscriptExit::default())
+            },
             State::Running(ref mut instance) => {
                 let mut pexit_code: PostscriptExitCode = 0;
-                let err = unsafe { gs_sys::ffi::gsapi_run_string_end(instance.as_raw_instance(), 0, &mut pexit_code) };
+                let user_errors = instance.user_errors.raw();
+                let err = {
+                    let _guard = self.job.enter();
+                    unsafe { gs_sys::ffi::gsapi_run_string_end(instance.as_raw_instance(), user_errors, &mut pexit_code) }
+                };
+                instance.resume_panic(&self.job);
 
-                InterpreterResult(ErrCode(err), pexit_code)
+                let result = self.job.map_result(InterpreterResult::from_raw(err, pexit_code));
+                let end_location = self.position.end();
+                self.record_error_location(result, end_location)
             },
             State::Completed(interpreter_result) => interpreter_result,
             State::Closed => unreachable!("Bug! This variant should never be visible outside of drop/close!"),
-        }
+        };
+        (result, self.error_location)
     }
 }
 
-impl<'a, T: 'a> Drop for GhostscriptStream<'a, T> {
+impl<'a, T: 'a, E: 'a> Drop for GhostscriptStream<'a, T, E> {
     fn drop(&mut self) {
-        if let State::Closed = self.0 {
+        if let State::Closed = self.state {
             return;
         };
 
-        let e = Self::close(::std::mem::replace(self, GhostscriptStream(State::Closed)));
+        let e = Self::close(::std::mem::replace(self, GhostscriptStream::from_state(State::Closed, ActiveJob::default())));
 
         debug!(
             "Dropped unclosed GhostscriptStream! Use explicit close() to collect errors. Close result: ({:?})",
@@ -110,3 +202,35 @@ impl<'a, T: 'a> Drop for GhostscriptStream<'a, T> {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::Utf8;
+    use std::io::Write;
+
+    #[test]
+    fn failing_chunk_location_counts_previous_chunks() {
+        let mut position = InputPosition::new();
+        assert_eq!(position.advance(b"%!PS\n1 2 add\n"), InputLocation { offset: 0, length: 13, line: 1 });
+        assert_eq!(position.advance(b"\n\n3 4 } add\n"), InputLocation { offset: 13, length: 12, line: 3 });
+        assert_eq!(position.end(), InputLocation { offset: 25, length: 0, line: 6 });
+
+        let failed = InterpreterResult(consts::SYNTAX_ERROR, PostscriptExit::default());
+        let error = failed.into_result_at(Some(position.end())).unwrap_err();
+        assert_eq!(error.location, Some(InputLocation { offset: 25, length: 0, line: 6 }));
+        assert!(error.to_string().ends_with(" at bytes 25..25 (line 6)"));
+    }
+
+    #[test]
+    fn write_error_of_failed_stream_downcasts_to_gs_error() {
+        let failed = InterpreterResult(consts::SYNTAX_ERROR, PostscriptExit::default());
+        let mut os = GhostscriptStream::<(), Utf8>::from_state(State::Completed(failed), ActiveJob::default());
+
+        let io_error = os.write(b"1 2 add").unwrap_err();
+        assert_eq!(io_error.get_ref().and_then(|e| e.downcast_ref::<GsError>()), Some(&GsError::SyntaxError));
+        let payload = io_error.into_inner().unwrap().downcast::<GsError>().unwrap();
+        assert_eq!(*payload, GsError::SyntaxError);
+        assert_eq!(os.close(), failed);
+    }
+}
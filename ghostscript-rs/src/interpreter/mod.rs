@@ -1,24 +1,385 @@
 mod gs;
 
-use DefaultEncoding as Encoding;
 use encoding::StringEncoding;
-use error::ErrCode;
+use error::{ErrCode, GsError};
 use gs_sys;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub type PostscriptExitCode = gs_sys::GsPExitCode;
 
+// The exit code, that the interpreter reports alongside the error code.
+// It is the operand of a `quit` (0 for a regular quit), or 255 after a fatal error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PostscriptExit {
+    Success,
+    Code(PostscriptExitCode),
+}
+
+impl PostscriptExit {
+    pub fn from_raw(code: PostscriptExitCode) -> Self {
+        match code {
+            0 => PostscriptExit::Success,
+            code => PostscriptExit::Code(code),
+        }
+    }
+
+    pub fn raw_code(&self) -> PostscriptExitCode {
+        match *self {
+            PostscriptExit::Success => 0,
+            PostscriptExit::Code(code) => code,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self == PostscriptExit::Success
+    }
+}
+
+impl ::std::default::Default for PostscriptExit {
+    fn default() -> Self {
+        PostscriptExit::Success
+    }
+}
+
+impl fmt::Display for PostscriptExit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PostscriptExit::Success => f.write_str("success"),
+            PostscriptExit::Code(code) => write!(f, "exit code {}", code),
+        }
+    }
+}
+
+// Controls the user_errors argument of gsapi_run_*() calls.
+// Ghostscript only checks, whether the argument is negative, so any other negative value
+// would behave like Return, and positive values like ErrorDict. Hence only these two.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum UserErrors {
+    // PostScript errors are handled by the errordict of the running job (user_errors = 0).
+    ErrorDict,
+    // PostScript errors bypass errordict and are returned directly to the caller (user_errors < 0).
+    Return,
+}
+
+impl UserErrors {
+    pub(crate) fn raw(&self) -> c_int {
+        match *self {
+            UserErrors::ErrorDict => 0,
+            UserErrors::Return => -1,
+        }
+    }
+}
+
+impl ::std::default::Default for UserErrors {
+    fn default() -> Self {
+        UserErrors::ErrorDict
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
-pub struct InterpreterResult(pub ErrCode, pub PostscriptExitCode);
+pub struct InterpreterResult(pub ErrCode, pub PostscriptExit);
+
+impl InterpreterResult {
+    pub(crate) fn from_raw(err: gs_sys::GsErrorType, pexit_code: PostscriptExitCode) -> Self {
+        InterpreterResult(ErrCode(err), PostscriptExit::from_raw(pexit_code))
+    }
+
+    pub fn into_result(self) -> Result<PostscriptExit, InterpreterError> {
+        self.into_result_at(None)
+    }
+
+    // Same as into_result(), with the location, that the stream or reader reported separately.
+    pub fn into_result_at(self, location: Option<InputLocation>) -> Result<PostscriptExit, InterpreterError> {
+        match self.0.to_result() {
+            Ok(()) => Ok(self.1),
+            Err(error) => Err(InterpreterError {
+                error,
+                exit_code: self.1,
+                location,
+            }),
+        }
+    }
+
+    // What write() of an interpreter stream returns, once the job has completed.
+    // After a quit the rest of the input is silently ignored.
+    pub(crate) fn into_write_result(self) -> io::Result<()> {
+        match self.into_result() {
+            Ok(_) => Ok(()),
+            Err(ref e) if e.error.is_quit() => Ok(()),
+            // The original GsError can be recovered with get_ref()/into_inner() and downcast.
+            // The location of the failing chunk is in error_location() of the stream.
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.error)),
+        }
+    }
+}
+
+// A region of the streamed program. Offset is counted in bytes from the start of the stream,
+// line is 1-based and is the line, on which the region starts.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct InputLocation {
+    pub offset: u64,
+    pub length: usize,
+    pub line: u64,
+}
+
+impl fmt::Display for InputLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bytes {}..{} (line {})",
+            self.offset,
+            self.offset + self.length as u64,
+            self.line
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct InterpreterError {
+    pub error: GsError,
+    pub exit_code: PostscriptExit,
+    // Where in the streamed input the interpreter failed, if known.
+    pub location: Option<InputLocation>,
+}
+
+impl From<InterpreterError> for InterpreterResult {
+    fn from(e: InterpreterError) -> Self {
+        InterpreterResult(e.error.code(), e.exit_code)
+    }
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Ghostscript interpreter failed ({}): {}",
+            self.exit_code, self.error
+        )?;
+        if let Some(location) = self.location {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for InterpreterError {
+    fn description(&self) -> &str {
+        "ghostscript interpreter failed"
+    }
+
+    fn source(&self) -> Option<&(Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Default)]
+pub struct ReaderOptions<'p> {
+    // Called after every chunk fed to the interpreter with the total number of bytes accepted so far.
+    pub progress: Option<&'p mut FnMut(u64)>,
+    // Checked before every chunk. Once set, no more data is read and the stream is closed.
+    pub cancel: Option<&'p AtomicBool>,
+}
+
+impl<'p> fmt::Debug for ReaderOptions<'p> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReaderOptions")
+            .field("progress", &self.progress.as_ref().map(|_| "FnMut(u64)"))
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ReaderResult {
+    pub result: InterpreterResult,
+    // Bytes delivered to the interpreter before it completed, failed, the reader ended or cancel was set.
+    pub bytes_accepted: u64,
+    pub cancelled: bool,
+    pub location: Option<InputLocation>,
+}
+
+impl ReaderResult {
+    pub fn into_result(self) -> Result<PostscriptExit, InterpreterError> {
+        self.result.into_result_at(self.location)
+    }
+}
+
+// The loop of Interpreter::interpret_reader(). Returns bytes accepted and whether cancel was set.
+// Each chunk is written until the stream has taken all of it, or refuses the rest, once the interpreter
+// has completed or failed. The error is then visible in close() result.
+pub(crate) fn feed_reader<'a, S, R>(os: &mut S, mut reader: R, opts: &mut ReaderOptions, chunk_size: usize) -> io::Result<(u64, bool)>
+where
+    S: InterpreterStream<'a>,
+    R: Read,
+{
+    let mut buf = vec![0u8; chunk_size];
+    let mut bytes_accepted: u64 = 0;
+
+    while !os.is_completed() {
+        if opts.cancel.map_or(false, |c| c.load(Ordering::SeqCst)) {
+            return Ok((bytes_accepted, true));
+        }
+
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        let mut chunk = &buf[..len];
+        while !chunk.is_empty() {
+            match os.write(chunk) {
+                Ok(0) => break,
+                Ok(written) => {
+                    bytes_accepted += written as u64;
+                    chunk = &chunk[written..];
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        if !chunk.is_empty() {
+            break;
+        }
+
+        if let Some(ref mut progress) = opts.progress {
+            progress(bytes_accepted);
+        }
+    }
+    Ok((bytes_accepted, false))
+}
 
 pub trait InterpreterStream<'a>: ::std::io::Write {
     fn is_completed(&self) -> bool;
+    fn error_location(&self) -> Option<InputLocation>;
     fn close(self) -> InterpreterResult;
 }
 
 pub trait Interpreter<'a> {
+    type Encoding: StringEncoding;
     type Stream: InterpreterStream<'a>;
 
-    fn open_interpreter_stream(&'a mut self) -> Result<Self::Stream, InterpreterResult>;
+    fn open_interpreter_stream(&'a mut self) -> Result<Self::Stream, InterpreterError>;
     fn interpret_buffer(&mut self, buffer: &[u8]) -> InterpreterResult;
-    fn interpret_file(&mut self, file_name: &<Encoding as StringEncoding>::RustType) -> InterpreterResult;
+    // Errors only if the reader does. Interpreter errors are reported in ReaderResult.
+    fn interpret_reader<R: Read>(&mut self, reader: R, opts: ReaderOptions) -> ::std::io::Result<ReaderResult>;
+    fn interpret_file<S>(&mut self, file_name: &S) -> InterpreterResult
+    where
+        S: AsRef<<Self::Encoding as StringEncoding>::RustType> + ?Sized;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::consts;
+
+    // Takes at most 4 bytes per write and fails with a syntax error in the write, that crosses fail_at.
+    struct FailingStream {
+        fail_at: u64,
+        fed: u64,
+        result: Option<InterpreterResult>,
+    }
+
+    impl ::std::io::Write for FailingStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = ::std::cmp::min(buf.len(), 4);
+            if self.result.is_none() && self.fed + len as u64 > self.fail_at {
+                self.result = Some(InterpreterResult(consts::SYNTAX_ERROR, PostscriptExit::default()));
+            }
+            match self.result {
+                Some(_) => Err(io::Error::new(io::ErrorKind::Other, GsError::SyntaxError)),
+                None => {
+                    self.fed += len as u64;
+                    Ok(len)
+                },
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> InterpreterStream<'a> for FailingStream {
+        fn is_completed(&self) -> bool {
+            self.result.is_some()
+        }
+
+        fn error_location(&self) -> Option<InputLocation> {
+            None
+        }
+
+        fn close(self) -> InterpreterResult {
+            self.result.unwrap_or(InterpreterResult(consts::OK, PostscriptExit::default()))
+        }
+    }
+
+    #[test]
+    fn user_errors_map_to_zero_and_negative() {
+        assert_eq!(UserErrors::default(), UserErrors::ErrorDict);
+        assert_eq!(UserErrors::ErrorDict.raw(), 0);
+        assert!(UserErrors::Return.raw() < 0);
+    }
+
+    #[test]
+    fn postscript_exit_round_trips_raw_codes() {
+        assert_eq!(PostscriptExit::from_raw(0), PostscriptExit::Success);
+        assert_eq!(PostscriptExit::default(), PostscriptExit::Success);
+        assert!(PostscriptExit::Success.is_success());
+        for &code in &[1, 255, -1, PostscriptExitCode::max_value(), PostscriptExitCode::min_value()] {
+            let exit = PostscriptExit::from_raw(code);
+            assert_eq!(exit, PostscriptExit::Code(code));
+            assert_eq!(exit.raw_code(), code);
+            assert!(!exit.is_success());
+        }
+        assert_eq!(PostscriptExit::from_raw(PostscriptExit::Success.raw_code()), PostscriptExit::Success);
+        assert_eq!(PostscriptExit::Code(255).to_string(), "exit code 255");
+    }
+
+    #[test]
+    fn partially_written_chunks_are_fed_whole() {
+        let mut os = FailingStream {
+            fail_at: u64::max_value(),
+            fed: 0,
+            result: None,
+        };
+        let mut progress = Vec::new();
+        let fed = {
+            let mut report = |accepted| progress.push(accepted);
+            let mut opts = ReaderOptions {
+                progress: Some(&mut report),
+                cancel: None,
+            };
+            feed_reader(&mut os, &b"1 2 add 3 4 add"[..], &mut opts, 10).unwrap()
+        };
+        assert_eq!(fed, (15, false));
+        assert_eq!(progress, vec![10, 15]);
+        assert_eq!(os.fed, 15);
+    }
+
+    #[test]
+    fn refused_chunk_is_not_accepted() {
+        let mut os = FailingStream {
+            fail_at: 10,
+            fed: 0,
+            result: None,
+        };
+        let mut progress = Vec::new();
+        let fed = {
+            let mut report = |accepted| progress.push(accepted);
+            let mut opts = ReaderOptions {
+                progress: Some(&mut report),
+                cancel: None,
+            };
+            feed_reader(&mut os, &b"1 2 add } 3 4 add"[..], &mut opts, 4).unwrap()
+        };
+        assert_eq!(fed, (8, false));
+        assert_eq!(progress, vec![4, 8]);
+        assert_eq!(os.close().0, consts::SYNTAX_ERROR);
+    }
 }
@@ -0,0 +1,267 @@
+// Settings and state of a running interpreter job.
+//
+// JobControl holds what a builder or an instance configures for its jobs: cancellation token,
+// timeout and deadline (see cancel module), resource limits (see limits module), the stdin reader
+// and the panic mode. Every job starts an ActiveJob from it, which callbacks find through
+// the thread-local current job.
+//
+// Cancellation, deadlines and limits are checked from the ghostscript poll callback, which the interpreter
+// calls periodically while it runs (provided the library was compiled with interrupt checks).
+// When one triggers, the poll callback interrupts the interpreter, and the job fails with
+// GsError::Cancelled, GsError::TimedOut or GsError::ResourceLimitExceeded instead of the generic INTERRUPT.
+// The first of these is latched: every later poll interrupts again, and the job fails with it,
+// even if the program catches the interrupt (e.g. with stopped) and completes. A program, that keeps catching
+// it in a loop, keeps running, since ghostscript can't be stopped from outside the interpreter.
+// Use process::GhostscriptProcess, which kills the process, for input, that can't be trusted.
+
+use callback::panic::{PanicMode, PanicPayload};
+use callback::stdio::reader::SharedReader;
+use cancel::{CancellationToken, Deadline};
+use error::GsError;
+use interpreter::InterpreterResult;
+use limits::{LimitTracker, ResourceLimits};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+// Cancellation settings of a builder or an instance.
+// Timeout is relative and restarts with every job, deadline is absolute.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JobControl {
+    pub(crate) token: Option<CancellationToken>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) deadline: Option<Deadline>,
+    pub(crate) limits: ResourceLimits,
+    pub(crate) stdin: Option<SharedReader>,
+    pub(crate) panic_mode: PanicMode,
+}
+
+impl JobControl {
+    // Settings of a new instance. The stdin reader moves from the builder into the instance.
+    pub(crate) fn for_instance(&self) -> JobControl {
+        JobControl {
+            stdin: self.stdin.as_ref().and_then(SharedReader::take),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn start(&self) -> ActiveJob {
+        let timeout = self.timeout.map(Deadline::after);
+        let deadline = match (self.deadline, timeout) {
+            (Some(a), Some(b)) => Some(::std::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        };
+
+        ActiveJob {
+            token: self.token.clone(),
+            deadline,
+            limits: LimitTracker::new(self.limits),
+            stdin: self.stdin.clone(),
+            panic: match self.panic_mode {
+                PanicMode::Handler => None,
+                PanicMode::Propagate => Some(Rc::default()),
+            },
+            interrupted: Rc::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ActiveJob {
+    token: Option<CancellationToken>,
+    deadline: Option<Deadline>,
+    limits: LimitTracker,
+    stdin: Option<SharedReader>,
+    // Payload of the first panic in a callback, waiting to be resumed in the caller.
+    panic: Option<Rc<RefCell<Option<PanicPayload>>>>,
+    // The first interruption, shared by all clones of the job.
+    interrupted: Rc<Cell<Option<GsError>>>,
+}
+
+impl ActiveJob {
+    // Once something interrupted the job, it is reported on every later check.
+    pub(crate) fn check(&self) -> Option<GsError> {
+        if let Some(error) = self.interrupted.get() {
+            return Some(error);
+        }
+        let error = if self.token.as_ref().map_or(false, CancellationToken::is_cancelled) {
+            Some(GsError::Cancelled)
+        } else if self.deadline.map_or(false, |d| d.has_passed()) {
+            Some(GsError::TimedOut)
+        } else {
+            self.limits.exceeded().map(GsError::ResourceLimitExceeded)
+        };
+        self.interrupted.set(error);
+        error
+    }
+
+    pub(crate) fn limits(&self) -> &LimitTracker {
+        &self.limits
+    }
+
+    pub(crate) fn stdin(&self) -> Option<&SharedReader> {
+        self.stdin.as_ref()
+    }
+
+    // Takes the payload, if the job propagates panics. Only the first panic is kept.
+    pub(crate) fn catch_panic(&self, callback_name: &'static str, error: &mut Option<PanicPayload>) {
+        if let Some(ref slot) = self.panic {
+            let mut slot = slot.borrow_mut();
+            if slot.is_none() {
+                debug!("Panic in ghostscript {} callback, failing the job", callback_name);
+                *slot = error.take();
+            } else {
+                debug!("Another panic in ghostscript {} callback, discarding it", callback_name);
+                error.take();
+            }
+        }
+    }
+
+    pub(crate) fn take_panic(&self) -> Option<PanicPayload> {
+        self.panic.as_ref().and_then(|slot| slot.borrow_mut().take())
+    }
+
+    // Makes the job visible to callbacks on this thread, until the guard is dropped.
+    pub(crate) fn enter(&self) -> JobGuard {
+        let previous = CURRENT_JOB.with(|j| ::std::mem::replace(&mut *j.borrow_mut(), Some(self.clone())));
+        JobGuard(previous)
+    }
+
+    // Interpreter errors after cancellation are the consequence of it, so report them as such.
+    pub(crate) fn map_error(&self, error: GsError) -> GsError {
+        if error.is_quit() {
+            return error;
+        }
+        self.check().unwrap_or(error)
+    }
+
+    // A job, that was interrupted, fails, even if the program caught the interrupt and completed.
+    pub(crate) fn map_result(&self, result: InterpreterResult) -> InterpreterResult {
+        match result.0.to_result() {
+            Ok(()) => self.interrupted.get().map_or(result, |e| InterpreterResult(e.code(), result.1)),
+            Err(e) => InterpreterResult(self.map_error(e).code(), result.1),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct JobGuard(Option<ActiveJob>);
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT_JOB.with(|j| *j.borrow_mut() = previous);
+    }
+}
+
+thread_local! {
+    // Poll callbacks are called synchronously from within gsapi_* calls,
+    // so the job, that is running on this thread, is the one being polled.
+    static CURRENT_JOB: RefCell<Option<ActiveJob>> = RefCell::new(None);
+}
+
+pub(crate) fn poll_current_job() -> Option<GsError> {
+    CURRENT_JOB.with(|j| j.borrow().as_ref().and_then(ActiveJob::check))
+}
+
+// Callbacks outside of any job (e.g. during instance destruction) get None.
+pub(crate) fn with_current_job<R, F: FnOnce(&ActiveJob) -> R>(f: F) -> Option<R> {
+    CURRENT_JOB.with(|j| j.borrow().as_ref().map(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::consts;
+    use interpreter::PostscriptExit;
+    use std::time::Instant;
+
+    fn interrupted() -> InterpreterResult {
+        InterpreterResult(consts::INTERRUPT, PostscriptExit::default())
+    }
+
+    #[test]
+    fn earliest_of_timeout_and_deadline_applies() {
+        let hour = Duration::from_secs(3600);
+        let mut control = JobControl {
+            timeout: Some(hour),
+            deadline: Some(Deadline::at(Instant::now())),
+            ..JobControl::default()
+        };
+        assert_eq!(control.start().deadline, control.deadline);
+
+        control.deadline = Some(Deadline::after(hour * 2));
+        let job = control.start();
+        assert!(job.deadline < control.deadline && job.deadline.is_some());
+
+        control.timeout = None;
+        assert_eq!(control.start().deadline, control.deadline);
+        assert_eq!(JobControl::default().start().deadline, None);
+    }
+
+    #[test]
+    fn interrupt_is_reported_as_its_cause() {
+        let token = CancellationToken::new();
+        let control = JobControl {
+            token: Some(token.clone()),
+            ..JobControl::default()
+        };
+        let job = control.start();
+        assert_eq!(job.map_result(interrupted()), interrupted());
+
+        token.cancel();
+        assert_eq!(job.map_result(interrupted()).0, consts::CANCELLED);
+
+        let expired = JobControl {
+            timeout: Some(Duration::from_secs(0)),
+            ..JobControl::default()
+        };
+        assert_eq!(expired.start().map_result(interrupted()).0, consts::TIMED_OUT);
+
+        let quit = InterpreterResult(consts::QUIT, PostscriptExit::default());
+        assert_eq!(expired.start().map_result(quit), quit);
+    }
+
+    #[test]
+    fn interruption_is_latched_for_the_whole_job() {
+        let token = CancellationToken::new();
+        let control = JobControl {
+            token: Some(token.clone()),
+            ..JobControl::default()
+        };
+        let job = control.start();
+        let current = job.clone();
+        assert_eq!(job.check(), None);
+
+        token.cancel();
+        assert_eq!(current.check(), Some(GsError::Cancelled));
+
+        // The program caught the interrupt and completed, it still fails.
+        let caught = InterpreterResult(consts::OK, PostscriptExit::default());
+        assert_eq!(job.map_result(caught).0, consts::CANCELLED);
+
+        // The next job starts clean.
+        let next = JobControl::default().start();
+        assert_eq!(next.map_result(caught), caught);
+    }
+
+    #[test]
+    fn current_job_is_visible_until_the_guard_drops() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let control = JobControl {
+            token: Some(token),
+            ..JobControl::default()
+        };
+        let job = control.start();
+
+        assert_eq!(poll_current_job(), None);
+        {
+            let _guard = job.enter();
+            assert_eq!(poll_current_job(), Some(GsError::Cancelled));
+            assert_eq!(with_current_job(|_| ()), Some(()));
+        }
+        assert_eq!(poll_current_job(), None);
+        assert_eq!(with_current_job(|_| ()), None);
+    }
+}
@@ -0,0 +1,225 @@
+// Many jobs on one long-lived instance, to pay for interpreter initialization
+// (fonts, resources) only once.
+//
+// The instance is started with -dJOBSERVER, and every job ends with "false 0 startjob",
+// the way a PostScript job server separates jobs. startjob restores the save, that ghostscript
+// took at the start of the job, and takes a new one for the next job. That save is the outermost one,
+// so it covers global VM too, and definitions made with "true setglobal" or in globaldict don't leak
+// either. It also clears the stacks and brings back the page device and graphics state of the server start.
+// The save object lives in ghostscript's own job server state, not in any dictionary this crate defines.
+//
+// startjob fails, when the job left a save of its own unrestored. The server can't get back
+// to a clean state then, and fails. So does it, when a job manages to quit the interpreter.
+
+use DefaultEncoding;
+use builder::{BuilderError, BuilderErrorKind, GhostscriptBuilder};
+use callback::CallbackSafe;
+use callback::panic::PanicCallback;
+use callback::stdio::{CaptureStdio, StdioCallback, WithStdio};
+use encoding::StringEncoding;
+use instance::Ghostscript;
+use interpreter::{Interpreter, InterpreterError, InterpreterResult};
+
+const JOBSERVER_PARAM: &str = "-dJOBSERVER";
+
+// Ends the current job and starts the next one. The raster isn't part of VM, so the page is erased explicitly.
+const JOB_BOUNDARY: &[u8] = b"erasepage false 0 startjob not { errordict /invalidrestore get exec } if\n";
+
+// Stdout and stderr are captured per job, so they are enabled regardless of the builder.
+fn server_builder<T, E>(builder: &GhostscriptBuilder<T, E>) -> GhostscriptBuilder<T, E>
+where
+    T: StdioCallback,
+    E: StringEncoding,
+    str: AsRef<E::RustType>,
+{
+    let mut builder = builder.clone();
+    builder.with_stdout(true).with_stderr(true).add_init_param(JOBSERVER_PARAM);
+    builder
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct JobOutput {
+    pub result: InterpreterResult,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct JobServer<Q, E = DefaultEncoding> {
+    instance: Ghostscript<Q, E>,
+    jobs_completed: u64,
+    // Set, when the encapsulation itself failed or a job quit. The server can't run jobs after that.
+    failure: Option<InterpreterError>,
+}
+
+impl<D, Q, E> JobServer<Q, E>
+where
+    D: PanicCallback,
+    Q: CallbackSafe<Target = WithStdio<D, CaptureStdio>>,
+    E: StringEncoding,
+{
+    pub fn start(builder: &GhostscriptBuilder<WithStdio<D, CaptureStdio>, E>, user_data: Q) -> Result<Self, BuilderError<Q>>
+    where
+        str: AsRef<E::RustType>,
+    {
+        let mut instance = server_builder(builder).build(user_data).running()?;
+        // Also checks, that the library supports job encapsulation at all.
+        if let Err(e) = instance.interpret_buffer(JOB_BOUNDARY).into_result() {
+            return Err(BuilderError::new(BuilderErrorKind::Initialization, e.error, instance.into_inner()));
+        }
+        instance.user_data_mut().stdio.take();
+
+        Ok(JobServer {
+            instance,
+            jobs_completed: 0,
+            failure: None,
+        })
+    }
+
+    // Errors of the job itself are in JobOutput::result. Err means, that the server is unusable.
+    pub fn run_job(&mut self, source: &[u8]) -> Result<JobOutput, InterpreterError> {
+        self.run_encapsulated(|instance| instance.interpret_buffer(source))
+    }
+
+    pub fn run_job_file<S>(&mut self, file_name: &S) -> Result<JobOutput, InterpreterError>
+    where
+        S: AsRef<E::RustType> + ?Sized,
+    {
+        self.run_encapsulated(|instance| instance.interpret_file(file_name))
+    }
+
+    fn run_encapsulated<F>(&mut self, job: F) -> Result<JobOutput, InterpreterError>
+    where
+        F: FnOnce(&mut Ghostscript<Q, E>) -> InterpreterResult,
+    {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        // Whatever the previous job boundary printed doesn't belong to this job.
+        self.instance.user_data_mut().stdio.take();
+
+        let result = job(&mut self.instance);
+        match result.into_result() {
+            // The interpreter is gone, but the output of the job is still valid.
+            Err(ref e) if e.error.is_quit() => {
+                self.fail(*e);
+            },
+            _ => {
+                if let Err(e) = self.instance.interpret_buffer(JOB_BOUNDARY).into_result() {
+                    return Err(self.fail(e));
+                }
+            },
+        }
+
+        let (stdout, stderr) = self.instance.user_data_mut().stdio.take();
+        self.jobs_completed += 1;
+        Ok(JobOutput { result, stdout, stderr })
+    }
+
+    fn fail(&mut self, error: InterpreterError) -> InterpreterError {
+        warn!("Ghostscript job server failed after {} jobs: {}", self.jobs_completed, error);
+        self.failure = Some(error);
+        error
+    }
+
+    pub fn jobs_completed(&self) -> u64 {
+        self.jobs_completed
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failure.is_some()
+    }
+
+    pub fn user_data_mut(&mut self) -> &mut D {
+        &mut self.instance.user_data_mut().data
+    }
+
+    pub fn into_inner(self) -> Q {
+        self.instance.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use callback::NoCallback;
+    use error::consts;
+
+    type Data = WithStdio<NoCallback, CaptureStdio>;
+
+    fn start() -> JobServer<Box<Data>> {
+        let mut builder = GhostscriptBuilder::<Data>::new();
+        builder.with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"]);
+        let data = Box::new(WithStdio::new(NoCallback, CaptureStdio::new(1 << 16)));
+        JobServer::start(&builder, data).expect("Job server failed to start")
+    }
+
+    #[test]
+    fn server_keeps_the_params_and_starts_ghostscript_as_job_server() {
+        let mut builder = GhostscriptBuilder::<Data>::new();
+        builder.with_init_params(&["-dNODISPLAY", "-dSAFER"]);
+        let server = server_builder(&builder);
+
+        let params: Vec<&[u8]> = server.init_params().iter().map(|p| p.as_ref().to_bytes()).collect();
+        assert_eq!(params, vec![&b"-dNODISPLAY"[..], b"-dSAFER", b"-dJOBSERVER"]);
+        assert_eq!(builder.init_params().len(), 2);
+    }
+
+    #[test]
+    fn job_boundary_starts_an_encapsulated_job_and_keeps_no_state_of_its_own() {
+        let boundary = String::from_utf8(JOB_BOUNDARY.to_vec()).unwrap();
+        let tokens: Vec<&str> = boundary.split_whitespace().collect();
+        // Not an unencapsulated one (true), and failing loudly, when startjob refuses.
+        let startjob = tokens.iter().position(|&t| t == "startjob").unwrap();
+        assert_eq!(&tokens[startjob - 2..startjob + 2], &["false", "0", "startjob", "not"]);
+        assert!(boundary.contains("errordict /invalidrestore get exec"));
+        assert_eq!(tokens[0], "erasepage");
+        for token in &["def", "put", "userdict", "serverdict", "save", "restore"] {
+            assert!(!tokens.contains(token), "boundary uses {}", token);
+        }
+        assert!(boundary.ends_with('\n'));
+    }
+
+    #[test]
+    #[ignore = "needs libgs, run with --ignored"]
+    fn definitions_of_a_job_dont_leak_into_the_next() {
+        let mut server = start();
+        let job = b"true setglobal /RustGlobalDef 1 def globaldict /RustGlobalPut 2 put false setglobal \
+userdict /RustLocalPut 3 put (defined) print flush";
+        let output = server.run_job(job).unwrap();
+        assert_eq!(output.result.0, consts::OK);
+        assert_eq!(output.stdout, b"defined".to_vec());
+
+        let check = b"[/RustGlobalDef /RustGlobalPut /RustLocalPut] { where { pop (leaked ) print } if } forall \
+globaldict /RustGlobalPut known { (leaked ) print } if (clean) print flush";
+        let output = server.run_job(check).unwrap();
+        assert_eq!(output.result.0, consts::OK);
+        assert_eq!(output.stdout, b"clean".to_vec());
+        assert_eq!(server.jobs_completed(), 2);
+    }
+
+    #[test]
+    #[ignore = "needs libgs, run with --ignored"]
+    fn failed_job_doesnt_affect_the_next() {
+        let mut server = start();
+        let stacks = b"count = countdictstack = flush";
+        let before = server.run_job(stacks).unwrap();
+
+        let output = server.run_job(b"1 2 3 0 dict begin 1 0 div").unwrap();
+        assert_eq!(output.result.0, consts::UNDEFINED_RESULT);
+
+        let after = server.run_job(stacks).unwrap();
+        assert_eq!(after.result.0, consts::OK);
+        assert_eq!(after.stdout, before.stdout);
+        assert!(!server.is_failed());
+    }
+
+    #[test]
+    #[ignore = "needs libgs, run with --ignored"]
+    fn unrestored_save_fails_the_server() {
+        let mut server = start();
+        let error = server.run_job(b"save pop").unwrap_err();
+        assert!(server.is_failed());
+        assert_eq!(server.run_job(b"(late) print").unwrap_err(), error);
+    }
+}
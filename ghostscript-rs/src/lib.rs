@@ -18,18 +18,34 @@ extern crate boolinator;
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "async")]
+extern crate tokio_util;
 
 extern crate ghostscript_sys;
 use ghostscript_sys as gs_sys;
 
+#[cfg(feature = "dlopen")]
+pub use gs_sys::dynamic;
+
 type DefaultEncoding = ::encoding::utf8::Utf8;
 
 pub const GS_OK: error::ErrCode = error::consts::OK;
 
+#[cfg(feature = "async")]
+pub mod async_io;
 pub mod builder;
 pub mod callback;
+pub mod cancel;
 pub mod device_list;
-mod encoding;
+pub mod encoding;
 pub mod error;
 pub mod instance;
 pub mod interpreter;
+mod job;
+pub mod job_server;
+pub mod limits;
+pub mod pool;
+pub mod process;
+pub mod worker;
@@ -0,0 +1,223 @@
+// Per-job resource limits, enforced by the display and stdio callbacks.
+//
+// Once a limit is exceeded, the offending callback fails, the poll callback interrupts
+// the interpreter, and the job fails with GsError::ResourceLimitExceeded(limit), even if
+// the PostScript program itself tries to recover from the callback failure.
+// Output volume can only be limited, if it goes through stdout/stderr callbacks.
+
+use error::{consts, ErrCode};
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct ResourceLimits {
+    pub max_pages: Option<u64>,
+    // Size of a single frame buffer, i.e. height * raster (row size in bytes).
+    pub max_frame_bytes: Option<u64>,
+    // Sum of all display_memalloc() requests during the job.
+    pub max_allocated_bytes: Option<u64>,
+    // Sum of stdout and stderr output during the job.
+    pub max_output_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        ResourceLimits::default()
+    }
+
+    pub fn with_max_pages(mut self, max_pages: u64) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    pub fn with_max_frame_bytes(mut self, max_frame_bytes: u64) -> Self {
+        self.max_frame_bytes = Some(max_frame_bytes);
+        self
+    }
+
+    pub fn with_max_allocated_bytes(mut self, max_allocated_bytes: u64) -> Self {
+        self.max_allocated_bytes = Some(max_allocated_bytes);
+        self
+    }
+
+    pub fn with_max_output_bytes(mut self, max_output_bytes: u64) -> Self {
+        self.max_output_bytes = Some(max_output_bytes);
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ResourceLimit {
+    Pages,
+    FrameBytes,
+    AllocatedBytes,
+    OutputBytes,
+}
+
+impl ResourceLimit {
+    pub(crate) fn code(&self) -> ErrCode {
+        match *self {
+            ResourceLimit::Pages => consts::PAGES_LIMIT_EXCEEDED,
+            ResourceLimit::FrameBytes => consts::FRAME_BYTES_LIMIT_EXCEEDED,
+            ResourceLimit::AllocatedBytes => consts::ALLOCATED_BYTES_LIMIT_EXCEEDED,
+            ResourceLimit::OutputBytes => consts::OUTPUT_BYTES_LIMIT_EXCEEDED,
+        }
+    }
+
+    pub(crate) fn from_code(code: ErrCode) -> Option<ResourceLimit> {
+        match code {
+            consts::PAGES_LIMIT_EXCEEDED => Some(ResourceLimit::Pages),
+            consts::FRAME_BYTES_LIMIT_EXCEEDED => Some(ResourceLimit::FrameBytes),
+            consts::ALLOCATED_BYTES_LIMIT_EXCEEDED => Some(ResourceLimit::AllocatedBytes),
+            consts::OUTPUT_BYTES_LIMIT_EXCEEDED => Some(ResourceLimit::OutputBytes),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ResourceUsage {
+    pages: Cell<u64>,
+    allocated_bytes: Cell<u64>,
+    output_bytes: Cell<u64>,
+    exceeded: Cell<Option<ResourceLimit>>,
+}
+
+// Limits together with the usage of the running job.
+// Clones share the usage, so that it accumulates across all gsapi calls of a job.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LimitTracker {
+    limits: ResourceLimits,
+    usage: Rc<ResourceUsage>,
+}
+
+impl LimitTracker {
+    pub(crate) fn new(limits: ResourceLimits) -> Self {
+        LimitTracker {
+            limits,
+            usage: Rc::default(),
+        }
+    }
+
+    pub(crate) fn exceeded(&self) -> Option<ResourceLimit> {
+        self.usage.exceeded.get()
+    }
+
+    // Only the first limit hit is logged and reported. Callbacks keep failing after it,
+    // e.g. for every further output chunk, until the job is interrupted.
+    fn exceed(&self, limit: ResourceLimit, requested: u64, max: u64) -> Result<(), ResourceLimit> {
+        if self.usage.exceeded.get().is_none() {
+            warn!("Ghostscript job exceeded {:?} limit: {} > {}", limit, requested, max);
+            self.usage.exceeded.set(Some(limit));
+        }
+        Err(limit)
+    }
+
+    fn add(&self, counter: &Cell<u64>, amount: u64, max: Option<u64>, limit: ResourceLimit) -> Result<(), ResourceLimit> {
+        let total = counter.get().saturating_add(amount);
+        match max {
+            Some(max) if total > max => self.exceed(limit, total, max),
+            _ => {
+                counter.set(total);
+                Ok(())
+            },
+        }
+    }
+
+    pub(crate) fn count_page(&self) -> Result<(), ResourceLimit> {
+        self.add(&self.usage.pages, 1, self.limits.max_pages, ResourceLimit::Pages)
+    }
+
+    pub(crate) fn check_frame(&self, height: usize, raster: usize) -> Result<(), ResourceLimit> {
+        let bytes = (height as u64).saturating_mul(raster as u64);
+        match self.limits.max_frame_bytes {
+            Some(max) if bytes > max => self.exceed(ResourceLimit::FrameBytes, bytes, max),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn count_allocation(&self, size: usize) -> Result<(), ResourceLimit> {
+        self.add(
+            &self.usage.allocated_bytes,
+            size as u64,
+            self.limits.max_allocated_bytes,
+            ResourceLimit::AllocatedBytes,
+        )
+    }
+
+    // Returns how many of len bytes may still be output.
+    pub(crate) fn count_output(&self, len: usize) -> usize {
+        let used = self.usage.output_bytes.get();
+        let allowed = match self.limits.max_output_bytes {
+            Some(max) => ::std::cmp::min(max.saturating_sub(used), len as u64) as usize,
+            None => len,
+        };
+        self.usage.output_bytes.set(used.saturating_add(len as u64));
+        if allowed < len {
+            let _ = self.exceed(
+                ResourceLimit::OutputBytes,
+                used.saturating_add(len as u64),
+                self.limits.max_output_bytes.unwrap_or(0),
+            );
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_tracker_counts_nothing_as_exceeded() {
+        let tracker = LimitTracker::new(ResourceLimits::new());
+        for _ in 0..100 {
+            assert_eq!(tracker.count_page(), Ok(()));
+        }
+        assert_eq!(tracker.check_frame(100_000, 100_000), Ok(()));
+        assert_eq!(tracker.count_allocation(usize::max_value()), Ok(()));
+        assert_eq!(tracker.count_output(1000), 1000);
+        assert_eq!(tracker.exceeded(), None);
+    }
+
+    #[test]
+    fn usage_accumulates_across_clones() {
+        let tracker = LimitTracker::new(ResourceLimits::new().with_max_pages(2).with_max_allocated_bytes(100));
+        let clone = tracker.clone();
+        assert_eq!(tracker.count_page(), Ok(()));
+        assert_eq!(clone.count_page(), Ok(()));
+        assert_eq!(tracker.count_page(), Err(ResourceLimit::Pages));
+        assert_eq!(clone.count_allocation(60), Ok(()));
+        assert_eq!(tracker.count_allocation(40), Ok(()));
+        assert_eq!(clone.count_allocation(1), Err(ResourceLimit::AllocatedBytes));
+        assert_eq!(tracker.exceeded(), Some(ResourceLimit::Pages));
+    }
+
+    #[test]
+    fn frame_limit_applies_to_single_frames() {
+        let tracker = LimitTracker::new(ResourceLimits::new().with_max_frame_bytes(1000));
+        assert_eq!(tracker.check_frame(10, 100), Ok(()));
+        assert_eq!(tracker.check_frame(10, 100), Ok(()));
+        assert_eq!(tracker.check_frame(11, 100), Err(ResourceLimit::FrameBytes));
+        assert_eq!(tracker.exceeded(), Some(ResourceLimit::FrameBytes));
+    }
+
+    #[test]
+    fn output_is_cut_at_the_limit() {
+        let tracker = LimitTracker::new(ResourceLimits::new().with_max_output_bytes(10));
+        assert_eq!(tracker.count_output(6), 6);
+        assert_eq!(tracker.exceeded(), None);
+        assert_eq!(tracker.count_output(6), 4);
+        assert_eq!(tracker.count_output(6), 0);
+        assert_eq!(tracker.exceeded(), Some(ResourceLimit::OutputBytes));
+    }
+
+    #[test]
+    fn first_exceeded_limit_is_kept() {
+        let tracker = LimitTracker::new(ResourceLimits::new().with_max_output_bytes(0).with_max_pages(0));
+        assert_eq!(tracker.count_output(1), 0);
+        assert_eq!(tracker.count_page(), Err(ResourceLimit::Pages));
+        assert_eq!(tracker.exceeded(), Some(ResourceLimit::OutputBytes));
+        assert_eq!(ResourceLimit::from_code(ResourceLimit::Pages.code()), Some(ResourceLimit::Pages));
+    }
+}
@@ -0,0 +1,427 @@
+// A pool of `gs` processes, for rendering in parallel with a library, that allows a single instance per process.
+//
+// Every worker thread hosts one GhostscriptProcess, that the build closure creates on that thread.
+// Jobs are queued to the pool, and taken in submission order by whichever worker is idle.
+// A worker, whose process has exited (it crashed, quit, or was killed by job control),
+// or whose job panicked, starts a new process before taking the next job.
+// Idle workers check their processes every IDLE_CHECK_INTERVAL, so health() doesn't report dead ones for long.
+// Frames come back over the pipes of the process into the user data of the worker,
+// e.g. with a raster device writing to -sOutputFile=%stdout and CaptureStdio.
+// Worker threads exit, once the last pool handle is dropped.
+//
+// The processes run the stock `gs` executable, not a helper, that hosts an instance of this crate.
+// So only what GhostscriptProcess supports is available in workers: stdio callbacks, job control
+// and file or %stdout output. Display, poll and other callbacks of the library don't exist there,
+// and devices, that need them (e.g. the display device), can't be used.
+
+use DefaultEncoding;
+use builder::{BuilderError, BuilderErrorKind};
+use callback::CallbackSafe;
+use encoding::StringEncoding;
+use error::GsError;
+use interpreter::{Interpreter, InterpreterResult};
+use process::GhostscriptProcess;
+use std::fmt;
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use worker::{JobReceiver, WorkerError};
+
+pub(crate) type PoolJob<Q, E> = Box<FnOnce(&mut GhostscriptProcess<Q, E>) + Send>;
+
+// Pause between attempts to restart a process, that fails to build.
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+// How long the worker waits for a job, before it checks, whether its process is still there.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum WorkerState {
+    Starting,
+    Idle,
+    Busy,
+    Restarting,
+    // The last attempt to (re)start the process failed. The worker keeps retrying.
+    Failed(BuilderErrorKind, GsError),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct WorkerHealth {
+    pub state: WorkerState,
+    pub pid: Option<u32>,
+    pub jobs_completed: u64,
+    pub restarts: u64,
+}
+
+impl ::std::default::Default for WorkerHealth {
+    fn default() -> Self {
+        WorkerHealth {
+            state: WorkerState::Starting,
+            pid: None,
+            jobs_completed: 0,
+            restarts: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolStatus {
+    queued: AtomicUsize,
+    workers: Mutex<Vec<WorkerHealth>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl PoolStatus {
+    fn update<F: FnOnce(&mut WorkerHealth)>(&self, index: usize, f: F) {
+        f(&mut lock(&self.workers)[index])
+    }
+}
+
+pub struct ProcessPool<Q, E = DefaultEncoding> {
+    // Plain Sender isn't Sync.
+    jobs: Arc<Mutex<Sender<PoolJob<Q, E>>>>,
+    status: Arc<PoolStatus>,
+}
+
+impl<Q, E> Clone for ProcessPool<Q, E> {
+    fn clone(&self) -> Self {
+        ProcessPool {
+            jobs: self.jobs.clone(),
+            status: self.status.clone(),
+        }
+    }
+}
+
+impl<Q, E> fmt::Debug for ProcessPool<Q, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProcessPool")
+            .field("handles", &Arc::strong_count(&self.jobs))
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+struct Worker<Q, E, F> {
+    index: usize,
+    build: Arc<F>,
+    queue: Arc<Mutex<Receiver<PoolJob<Q, E>>>>,
+    status: Arc<PoolStatus>,
+    // Restarts are given up, once the pool is gone.
+    pool: Weak<Mutex<Sender<PoolJob<Q, E>>>>,
+}
+
+impl<Q, E, F> Worker<Q, E, F>
+where
+    E: StringEncoding,
+    F: Fn() -> Result<GhostscriptProcess<Q, E>, BuilderError<Q>>,
+{
+    fn build(&self) -> Result<GhostscriptProcess<Q, E>, WorkerError> {
+        match (self.build)() {
+            Ok(process) => {
+                let pid = process.pid();
+                self.status.update(self.index, |h| {
+                    h.state = WorkerState::Idle;
+                    h.pid = Some(pid);
+                });
+                Ok(process)
+            },
+            Err(e) => {
+                let (kind, error) = e.kind_and_error();
+                self.status.update(self.index, |h| {
+                    h.state = WorkerState::Failed(kind, error);
+                    h.pid = None;
+                });
+                Err(WorkerError::Build(kind, error))
+            },
+        }
+    }
+
+    fn restart(&self, process: GhostscriptProcess<Q, E>) -> Option<GhostscriptProcess<Q, E>> {
+        debug!("Restarting ghostscript pool worker {} (process {})", self.index, process.pid());
+        ::std::mem::drop(process);
+        self.status.update(self.index, |h| {
+            h.state = WorkerState::Restarting;
+            h.restarts += 1;
+        });
+        while self.pool.upgrade().is_some() {
+            match self.build() {
+                Ok(process) => return Some(process),
+                Err(e) => {
+                    warn!("Failed to restart ghostscript pool worker {}: {}", self.index, e);
+                    thread::sleep(RESTART_BACKOFF);
+                },
+            }
+        }
+        None
+    }
+
+    fn run<N: FnOnce(Result<(), WorkerError>)>(self, started: N) {
+        let mut process = match self.build() {
+            Ok(process) => process,
+            Err(e) => return started(Err(e)),
+        };
+        started(Ok(()));
+
+        loop {
+            // Idle workers wait for the lock, the one holding it waits for the next job.
+            let job = match lock(&self.queue).recv_timeout(IDLE_CHECK_INTERVAL) {
+                Ok(job) => Some(job),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if process.poll_exited() {
+                debug!("Ghostscript pool worker {} process {} has exited while idle", self.index, process.pid());
+                process = match self.restart(process) {
+                    Some(process) => process,
+                    None => break,
+                };
+            }
+            let job = match job {
+                Some(job) => job,
+                None => continue,
+            };
+            self.status.queued.fetch_sub(1, Ordering::SeqCst);
+            self.status.update(self.index, |h| h.state = WorkerState::Busy);
+
+            // The receiver of a panicked job sees Disconnected.
+            let panicked = catch_unwind(AssertUnwindSafe(|| job(&mut process))).is_err();
+            if !panicked {
+                self.status.update(self.index, |h| h.jobs_completed += 1);
+            }
+
+            if panicked || process.has_exited() {
+                process = match self.restart(process) {
+                    Some(process) => process,
+                    None => break,
+                };
+            } else {
+                self.status.update(self.index, |h| h.state = WorkerState::Idle);
+            }
+        }
+        debug!("All ghostscript pool handles are dropped, shutting down worker {}", self.index);
+    }
+}
+
+impl<Q: 'static, E: StringEncoding + 'static> ProcessPool<Q, E> {
+    // Runs build on every worker thread, so the user data is created there and never leaves it.
+    // Returns after all the processes are started, or with the first error.
+    // A pool without workers would never run a job, so zero workers fail with Spawn(InvalidInput).
+    pub fn spawn<F>(workers: usize, build: F) -> Result<Self, WorkerError>
+    where
+        F: Fn() -> Result<GhostscriptProcess<Q, E>, BuilderError<Q>> + Send + Sync + 'static,
+    {
+        if workers == 0 {
+            return Err(WorkerError::Spawn(io::ErrorKind::InvalidInput));
+        }
+        let (jobs, job_queue) = channel::<PoolJob<Q, E>>();
+        let pool = ProcessPool {
+            jobs: Arc::new(Mutex::new(jobs)),
+            status: Arc::new(PoolStatus {
+                queued: AtomicUsize::new(0),
+                workers: Mutex::new(vec![WorkerHealth::default(); workers]),
+            }),
+        };
+        let build = Arc::new(build);
+        let queue = Arc::new(Mutex::new(job_queue));
+        let (started, startup) = channel();
+
+        for index in 0..workers {
+            let worker = Worker {
+                index,
+                build: build.clone(),
+                queue: queue.clone(),
+                status: pool.status.clone(),
+                pool: Arc::downgrade(&pool.jobs),
+            };
+            let started = started.clone();
+            thread::Builder::new()
+                .name(format!("ghostscript-pool-{}", index))
+                .spawn(move || {
+                    worker.run(move |result| {
+                        let _ = started.send(result);
+                    })
+                })
+                .map_err(|e| WorkerError::Spawn(e.kind()))?;
+        }
+        ::std::mem::drop(started);
+
+        for _ in 0..workers {
+            startup.recv().unwrap_or(Err(WorkerError::Disconnected))?;
+        }
+        Ok(pool)
+    }
+
+    pub fn size(&self) -> usize {
+        lock(&self.status.workers).len()
+    }
+
+    // Jobs, that no worker has taken yet.
+    pub fn queue_depth(&self) -> usize {
+        self.status.queued.load(Ordering::SeqCst)
+    }
+
+    pub fn health(&self) -> Vec<WorkerHealth> {
+        lock(&self.status.workers).clone()
+    }
+
+    fn submit(&self, job: PoolJob<Q, E>) {
+        self.status.queued.fetch_add(1, Ordering::SeqCst);
+        if lock(&self.jobs).send(job).is_err() {
+            // The job is dropped along with its result sender, so the receiver sees Disconnected.
+            self.status.queued.fetch_sub(1, Ordering::SeqCst);
+            debug!("Ghostscript pool workers are gone, dropping the job");
+        }
+    }
+
+    // Runs any code against the process of whichever worker takes the job.
+    pub fn run<R, F>(&self, job: F) -> JobReceiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut GhostscriptProcess<Q, E>) -> R + Send + 'static,
+    {
+        let (result, receiver) = channel();
+        self.submit(Box::new(move |process: &mut GhostscriptProcess<Q, E>| {
+            let _ = result.send(job(process));
+        }));
+        JobReceiver(receiver)
+    }
+
+    pub fn interpret_buffer(&self, buffer: Vec<u8>) -> JobReceiver<InterpreterResult> {
+        self.run(move |process| process.interpret_buffer(&buffer))
+    }
+
+    pub fn interpret_file<S>(&self, file_name: S) -> JobReceiver<InterpreterResult>
+    where
+        S: AsRef<E::RustType> + Send + 'static,
+    {
+        self.run(move |process| process.interpret_file(&file_name))
+    }
+
+    // See GhostscriptWorker::render(). The user data of every worker should be reset by collect.
+    pub fn render<S, R, F>(&self, file_name: S, collect: F) -> JobReceiver<(InterpreterResult, R)>
+    where
+        Q: CallbackSafe,
+        Q::Target: Sized,
+        S: AsRef<E::RustType> + Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut Q::Target) -> R + Send + 'static,
+    {
+        self.run(move |process| {
+            let result = process.interpret_file(&file_name);
+            (result, collect(process.user_data_mut()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::GhostscriptBuilder;
+    use callback::NoCallback;
+    use error::consts;
+    use process::{executable_available, DEFAULT_EXECUTABLE};
+    use std::process::Command;
+    use std::time::Instant;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn pool_is_send_and_sync_for_thread_bound_user_data() {
+        assert_send_sync::<ProcessPool<Box<::std::rc::Rc<()>>>>();
+    }
+
+    #[test]
+    fn pool_without_workers_is_rejected() {
+        let spawned = ProcessPool::<NoCallback>::spawn(0, build);
+        assert_eq!(spawned.err(), Some(WorkerError::Spawn(io::ErrorKind::InvalidInput)));
+    }
+
+    #[test]
+    fn queue_depth_counts_jobs_until_taken() {
+        let (jobs, job_queue) = channel::<PoolJob<NoCallback, DefaultEncoding>>();
+        let pool = ProcessPool {
+            jobs: Arc::new(Mutex::new(jobs)),
+            status: Arc::new(PoolStatus {
+                queued: AtomicUsize::new(0),
+                workers: Mutex::new(Vec::new()),
+            }),
+        };
+        let _first = pool.interpret_buffer(Vec::new());
+        let _second = pool.interpret_buffer(Vec::new());
+        assert_eq!(pool.queue_depth(), 2);
+
+        // Jobs, that can't be queued anymore, are not counted.
+        ::std::mem::drop(job_queue);
+        let third = pool.interpret_buffer(Vec::new());
+        assert_eq!(pool.queue_depth(), 2);
+        assert_eq!(third.wait(), Err(WorkerError::Disconnected));
+    }
+
+    fn build() -> Result<GhostscriptProcess<NoCallback>, BuilderError<NoCallback>> {
+        let mut builder = GhostscriptBuilder::<()>::new();
+        builder.with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"]);
+        builder.build_process(DEFAULT_EXECUTABLE, NoCallback)
+    }
+
+    fn wait_for<F: Fn(&WorkerHealth) -> bool>(pool: &ProcessPool<NoCallback>, condition: F) -> WorkerHealth {
+        let started = Instant::now();
+        loop {
+            let health = pool.health()[0];
+            if condition(&health) {
+                return health;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "Pool worker is stuck at {:?}", health);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn crashed_and_panicked_workers_are_restarted() {
+        if !executable_available() {
+            return;
+        }
+        let pool = ProcessPool::spawn(1, build).unwrap();
+        let first = wait_for(&pool, |h| h.state == WorkerState::Idle);
+
+        // Killed while idle.
+        let pid = first.pid.unwrap().to_string();
+        assert!(Command::new("kill").args(&["-KILL", &pid]).status().unwrap().success());
+        let restarted = wait_for(&pool, |h| h.restarts == 1 && h.state == WorkerState::Idle);
+        assert_ne!(restarted.pid, first.pid);
+        assert_eq!(pool.interpret_buffer(b"1 2 add pop".to_vec()).wait().unwrap().0, consts::OK);
+        assert_eq!(pool.health()[0].jobs_completed, 1);
+
+        let panicked = pool.run(|_| -> () { panic!("job") });
+        assert_eq!(panicked.wait(), Err(WorkerError::Disconnected));
+        let restarted = wait_for(&pool, |h| h.restarts == 2 && h.state == WorkerState::Idle);
+        assert_eq!(restarted.jobs_completed, 1);
+    }
+
+    #[test]
+    fn queue_depth_counts_jobs_behind_a_busy_worker() {
+        if !executable_available() {
+            return;
+        }
+        let pool = ProcessPool::spawn(1, build).unwrap();
+        let (release, released) = channel::<()>();
+        let busy = pool.run(move |_| released.recv().is_ok());
+        wait_for(&pool, |h| h.state == WorkerState::Busy);
+
+        let waiting: Vec<_> = (0..3).map(|_| pool.interpret_buffer(b"1 pop".to_vec())).collect();
+        assert_eq!(pool.queue_depth(), 3);
+        release.send(()).unwrap();
+        assert_eq!(busy.wait(), Ok(true));
+        for job in waiting {
+            assert_eq!(job.wait().unwrap().0, consts::OK);
+        }
+        assert_eq!(pool.queue_depth(), 0);
+        assert_eq!(pool.health()[0].jobs_completed, 4);
+    }
+}
@@ -0,0 +1,850 @@
+// Interpreter in a separate `gs` process, driven over pipes, for isolation from interpreter crashes.
+//
+// The process gets the init params of the builder, followed by "-", so it executes whatever
+// arrives on its stdin. Every job is streamed through a SubFileDecode filter, that ends at
+// a per-process marker, and is run in a stopped context. Afterwards the process prints
+// a completion marker with the error name to stdout and stderr, which separates
+// the output of consecutive jobs.
+//
+// Stdout, stderr and poll callbacks of the builder are called on the calling thread,
+// under the usual job control. Display callbacks, stdin and the default device list
+// can't be served over the pipes. Pages can be rendered with a raster device to -sOutputFile=%stdout
+// instead, and decoded from the stdout output. A cancelled, timed out or overlimit job can't be interrupted,
+// so the process is killed, and every later job fails with the same error.
+// The job is written to the process by another thread, so job control is checked also while
+// a job, that doesn't read its input (e.g. an endless loop), keeps a write waiting.
+
+use DefaultEncoding;
+use builder::{BuilderError, BuilderErrorKind};
+use callback::CallbackSafe;
+use callback::poll::ffi_callbacks::Poll;
+use callback::stdio::ffi_callbacks::Output;
+use encoding::StringEncoding;
+use error::{consts, ErrCode, GsError};
+use interpreter::{feed_reader, InputLocation, Interpreter, InterpreterError, InterpreterResult, InterpreterStream, PostscriptExit};
+use interpreter::{ReaderOptions, ReaderResult, UserErrors};
+use job::{ActiveJob, JobControl};
+use std::ffi::{CStr, OsStr, OsString};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_void};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(windows)]
+pub const DEFAULT_EXECUTABLE: &str = "gswin64c";
+#[cfg(not(windows))]
+pub const DEFAULT_EXECUTABLE: &str = "gs";
+
+// Same chunk size as the in-process interpreter takes in one run_string() call.
+const MAX_BYTES_PER_WRITE: usize = 65_535;
+const READ_CHUNK_LEN: usize = 4096;
+// How often job control and the poll callback are checked, while the process is busy.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long a dropped process may take to exit on its own, before it is killed.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+// Exit code, that is reported for a process, that crashed instead of exiting.
+const CRASH_EXIT_CODE: c_int = 255;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Pipe {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug)]
+enum PipeEvent {
+    Data(Pipe, Vec<u8>),
+    Closed(Pipe),
+    // The process took the last chunk sent to the writer, or writing it failed.
+    Written(io::Result<()>),
+}
+
+// FFI callbacks of the builder, called with the user data handle, just like the library would.
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct ProcessCallbacks {
+    pub(crate) poll: Option<Poll>,
+    pub(crate) stdout: Option<Output>,
+    pub(crate) stderr: Option<Output>,
+}
+
+// Per-process strings, that jobs are not going to contain by accident.
+#[derive(Debug, Clone)]
+struct Markers {
+    end_of_job: String,
+    done: String,
+}
+
+impl Markers {
+    fn new(pid: u32) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() ^ u64::from(d.subsec_nanos()))
+            .unwrap_or(0);
+        Markers {
+            end_of_job: format!("RustGsEndOfJob{:x}x{:x}", pid, nanos),
+            done: format!("%%RustGsDone{:x}x{:x}:", pid, nanos),
+        }
+    }
+
+    // Starts reading the job from stdin. The trailing newline is the last byte the scanner takes,
+    // so the job starts right after it, and ends at the end_of_job marker.
+    fn job_prologue(&self, user_errors: UserErrors) -> String {
+        let report_error = match user_errors {
+            UserErrors::ErrorDict => "handleerror ",
+            UserErrors::Return => "",
+        };
+        format!(
+            "userdict /RustGsJob currentfile << /EODString ({eod}) /EODCount 0 >> /SubFileDecode filter put \
+             {{ userdict /RustGsJob get cvx stopped \
+             {{ $error /newerror get {{ {report}$error /errorname get $error /newerror false put }} {{ null }} ifelse }} \
+             {{ null }} ifelse \
+             userdict /RustGsJob get flushfile \
+             (%stderr) (w) file dup ({done}\\n) writestring flushfile \
+             ({done}) print ==only (\\n) print flush }} exec\n",
+            eod = self.end_of_job,
+            report = report_error,
+            done = self.done,
+        )
+    }
+
+    fn job_epilogue(&self) -> String {
+        format!("{}\n", self.end_of_job)
+    }
+}
+
+// Output of one pipe, that waits to be forwarded to the callback.
+#[derive(Debug, Default)]
+struct PipeOutput {
+    pending: Vec<u8>,
+    closed: bool,
+}
+
+impl PipeOutput {
+    // Splits off the output, that is ready to be forwarded, and the status after the marker, if it has arrived.
+    // The tail, that may turn out to be the start of the marker, is held back.
+    fn split(&mut self, marker: &[u8]) -> (Vec<u8>, Option<Vec<u8>>) {
+        if let Some(start) = self.pending.windows(marker.len()).position(|w| w == marker) {
+            let status_start = start + marker.len();
+            if let Some(len) = self.pending[status_start..].iter().position(|&b| b == b'\n') {
+                let rest = self.pending.split_off(status_start + len + 1);
+                let status = self.pending[status_start..status_start + len].to_vec();
+                self.pending.truncate(start);
+                let ready = ::std::mem::replace(&mut self.pending, rest);
+                return (ready, Some(status));
+            }
+            let rest = self.pending.split_off(start);
+            return (::std::mem::replace(&mut self.pending, rest), None);
+        }
+        let keep = if self.closed { 0 } else { marker.len() - 1 };
+        let rest = self.pending.split_off(self.pending.len().saturating_sub(keep));
+        (::std::mem::replace(&mut self.pending, rest), None)
+    }
+}
+
+// Completion status, that the job prologue prints: null, or the name of the error.
+fn parse_status(status: &[u8]) -> ErrCode {
+    let status = String::from_utf8_lossy(status);
+    let name = status.trim();
+    if name == "null" {
+        return consts::OK;
+    }
+    let name = name.trim_start_matches('/');
+    (-64..0)
+        .filter_map(GsError::from_raw)
+        .find(|e| e.name().replace('_', "").eq_ignore_ascii_case(name))
+        .unwrap_or(GsError::UnknownError)
+        .code()
+}
+
+// Escapes a file name for a PostScript string literal.
+fn postscript_string(s: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(s.len() + 2);
+    escaped.push(b'(');
+    for &b in s {
+        if b == b'(' || b == b')' || b == b'\\' {
+            escaped.push(b'\\');
+        }
+        escaped.push(b);
+    }
+    escaped.push(b')');
+    escaped
+}
+
+#[cfg(unix)]
+fn ffi_to_arg(s: &CStr) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    OsStr::from_bytes(s.to_bytes()).to_owned()
+}
+
+#[cfg(not(unix))]
+fn ffi_to_arg(s: &CStr) -> OsString {
+    s.to_string_lossy().into_owned().into()
+}
+
+fn spawn_reader<R: Read + Send + 'static>(pipe: Pipe, mut reader: R, events: Sender<PipeEvent>) -> io::Result<()> {
+    thread::Builder::new()
+        .name(format!("ghostscript-process-{:?}", pipe).to_lowercase())
+        .spawn(move || {
+            let mut buf = vec![0u8; READ_CHUNK_LEN];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(len) => if events.send(PipeEvent::Data(pipe, buf[..len].to_vec())).is_err() {
+                        return;
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        debug!("Reading ghostscript process {:?} failed: {}", pipe, e);
+                        break;
+                    },
+                }
+            }
+            let _ = events.send(PipeEvent::Closed(pipe));
+        })
+        .map(|_| ())
+}
+
+fn spawn_writer(mut stdin: ChildStdin, events: Sender<PipeEvent>) -> io::Result<Sender<Vec<u8>>> {
+    let (chunks, chunk_queue) = mpsc::channel::<Vec<u8>>();
+    thread::Builder::new()
+        .name("ghostscript-process-stdin".into())
+        .spawn(move || {
+            // Ends, once the process is dropped, killed or has exited. Closing stdin ends the input of the process.
+            for chunk in chunk_queue {
+                let written = stdin.write_all(&chunk).and_then(|()| stdin.flush());
+                let failed = written.is_err();
+                if events.send(PipeEvent::Written(written)).is_err() || failed {
+                    return;
+                }
+            }
+        })
+        .map(|_| chunks)
+}
+
+#[derive(Debug)]
+pub struct GhostscriptProcess<T, E = DefaultEncoding> {
+    child: Child,
+    // Chunks for the writer thread.
+    stdin: Option<Sender<Vec<u8>>>,
+    // Whether a chunk waits for the writer thread to write it, and how the last one went.
+    writing: bool,
+    write_error: Option<io::Error>,
+    events: Receiver<PipeEvent>,
+    stdout: PipeOutput,
+    stderr: PipeOutput,
+    markers: Markers,
+    callbacks: ProcessCallbacks,
+    user_errors: UserErrors,
+    pub(crate) job_control: JobControl,
+    // Result of the job, that ended the process. Every later job reports it again.
+    exited: Option<InterpreterResult>,
+    handle: *mut c_void,
+    user_data: Option<T>,
+    _encoding: PhantomData<E>,
+}
+
+impl<T, E> GhostscriptProcess<T, E> {
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited.is_some()
+    }
+
+    // Notices a process, that has ended between jobs, e.g. killed from outside.
+    // has_exited() only knows about the ones, that ended during a job.
+    pub fn poll_exited(&mut self) -> bool {
+        if self.exited.is_none() {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                self.exit();
+            }
+        }
+        self.has_exited()
+    }
+
+    pub fn into_inner(mut self) -> T {
+        self.user_data.take().expect("Bug! user_data is missing.")
+    }
+
+    fn check_exited(&self) -> Result<(), InterpreterError> {
+        match self.exited {
+            Some(result) => result.into_result().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn receive(&mut self, event: PipeEvent) {
+        match event {
+            PipeEvent::Data(Pipe::Stdout, data) => self.stdout.pending.extend_from_slice(&data),
+            PipeEvent::Data(Pipe::Stderr, data) => self.stderr.pending.extend_from_slice(&data),
+            PipeEvent::Closed(Pipe::Stdout) => self.stdout.closed = true,
+            PipeEvent::Closed(Pipe::Stderr) => self.stderr.closed = true,
+            PipeEvent::Written(written) => {
+                self.writing = false;
+                self.write_error = written.err();
+            },
+        }
+    }
+
+    // Waits at most POLL_INTERVAL for output or for the writer thread.
+    fn wait_event(&mut self) {
+        match self.events.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                self.receive(event);
+                self.receive_pending();
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => {
+                self.stdout.closed = true;
+                self.stderr.closed = true;
+                self.writing = false;
+            },
+        }
+    }
+
+    fn receive_pending(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => self.receive(event),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.stdout.closed = true;
+                    self.stderr.closed = true;
+                    self.writing = false;
+                    return;
+                },
+            }
+        }
+    }
+
+    // Hands the output over to the callback, and returns the completion status, if it has arrived.
+    fn forward(&mut self, pipe: Pipe) -> Option<Vec<u8>> {
+        let (ready, status) = match pipe {
+            Pipe::Stdout => self.stdout.split(self.markers.done.as_bytes()),
+            Pipe::Stderr => self.stderr.split(self.markers.done.as_bytes()),
+        };
+        if !ready.is_empty() {
+            self.write_output(pipe, &ready);
+        }
+        status
+    }
+
+    fn write_output(&mut self, pipe: Pipe, mut data: &[u8]) {
+        let callback = match pipe {
+            Pipe::Stdout => self.callbacks.stdout,
+            Pipe::Stderr => self.callbacks.stderr,
+        };
+        let callback = match callback {
+            Some(callback) => callback,
+            // Without a callback the library writes to the stdio of the process too.
+            None => {
+                let _ = match pipe {
+                    Pipe::Stdout => io::stdout().write_all(data),
+                    Pipe::Stderr => io::stderr().write_all(data),
+                };
+                return;
+            },
+        };
+        while !data.is_empty() {
+            let len = ::std::cmp::min(data.len(), c_int::max_value() as usize);
+            let written = unsafe { callback(self.handle, data.as_ptr() as *const c_char, len as c_int) };
+            if written <= 0 {
+                debug!("Ghostscript process {:?} callback refused {} bytes", pipe, data.len());
+                return;
+            }
+            data = &data[::std::cmp::min(written as usize, len)..];
+        }
+    }
+
+    fn interruption(&self, job: &ActiveJob) -> Option<GsError> {
+        job.check().or_else(|| {
+            let poll = self.callbacks.poll?;
+            ErrCode(unsafe { poll(self.handle) }).to_result().err()
+        })
+    }
+
+    fn kill(&mut self, error: GsError) -> InterpreterResult {
+        debug!("Killing ghostscript process {}: {}", self.child.id(), error);
+        // The writer thread ends with the write, that the process doesn't take, once it is dead.
+        self.stdin = None;
+        self.writing = false;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let result = InterpreterResult(error.code(), PostscriptExit::default());
+        self.exited = Some(result);
+        result
+    }
+
+    // The process has ended on its own, either with a quit or with a crash.
+    fn exit(&mut self) -> InterpreterResult {
+        self.stdin = None;
+        let result = match self.child.wait().map(|status| status.code()) {
+            Ok(Some(0)) => InterpreterResult(consts::QUIT, PostscriptExit::Success),
+            Ok(Some(code)) => InterpreterResult(consts::FATAL, PostscriptExit::from_raw(code)),
+            Ok(None) | Err(_) => InterpreterResult(consts::FATAL, PostscriptExit::from_raw(CRASH_EXIT_CODE)),
+        };
+        debug!("Ghostscript process {} has exited: {:?}", self.child.id(), result);
+        self.exited = Some(result);
+        result
+    }
+
+    // Writes a part of the job, while forwarding the output, that the job produces meanwhile.
+    // A chunk is written by the writer thread, and waited for, so that job control is checked,
+    // even when the process doesn't read it.
+    fn send(&mut self, data: &[u8], job: &ActiveJob) -> Result<(), InterpreterResult> {
+        for chunk in data.chunks(READ_CHUNK_LEN) {
+            self.receive_pending();
+            self.writing = self.stdin.as_ref().map_or(false, |stdin| stdin.send(chunk.to_vec()).is_ok());
+            if !self.writing {
+                debug!("Ghostscript process {} stdin is closed", self.child.id());
+                return Err(self.finish_job(job));
+            }
+            while self.writing {
+                self.forward(Pipe::Stdout);
+                self.forward(Pipe::Stderr);
+                if let Some(e) = self.interruption(job) {
+                    return Err(self.kill(e));
+                }
+                self.wait_event();
+            }
+            if let Some(e) = self.write_error.take() {
+                debug!("Writing to ghostscript process failed: {}", e);
+                return Err(self.finish_job(job));
+            }
+        }
+        Ok(())
+    }
+
+    // Forwards the output, until both completion markers arrive, or the process ends.
+    fn finish_job(&mut self, job: &ActiveJob) -> InterpreterResult {
+        let mut status = None;
+        let mut stderr_done = false;
+        loop {
+            self.receive_pending();
+            if status.is_none() {
+                status = self.forward(Pipe::Stdout);
+            }
+            if !stderr_done {
+                stderr_done = self.forward(Pipe::Stderr).is_some();
+            }
+            if let (&Some(ref status), true) = (&status, stderr_done) {
+                return InterpreterResult(parse_status(status), PostscriptExit::Success);
+            }
+            if self.stdout.closed && self.stderr.closed {
+                return self.exit();
+            }
+            if let Some(e) = self.interruption(job) {
+                return self.kill(e);
+            }
+            self.wait_event();
+        }
+    }
+
+    // A callback panic leaves the job half-way, so the process is of no further use.
+    fn resume_panic(&mut self, job: &ActiveJob) {
+        if let Some(payload) = job.take_panic() {
+            self.kill(GsError::Poisoned);
+            if !thread::panicking() {
+                ::std::panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+impl<Q: CallbackSafe, E: StringEncoding> GhostscriptProcess<Q, E>
+where
+    Q::Target: Sized,
+{
+    pub(crate) fn start(
+        executable: &OsStr,
+        init_params: &[E::FfiType],
+        user_errors: UserErrors,
+        job_control: JobControl,
+        callbacks: ProcessCallbacks,
+        mut user_data: Q,
+    ) -> Result<Self, BuilderError<Q>> {
+        let spawned = Command::new(executable)
+            .args(init_params.iter().map(|p| ffi_to_arg(p.as_ref())))
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                warn!("Failed to start ghostscript process {:?}: {}", executable, e);
+                return Err(BuilderError::new(BuilderErrorKind::Process, GsError::Fatal, user_data));
+            },
+        };
+
+        let (sender, events) = mpsc::channel();
+        let stdout = child.stdout.take().expect("Bug! stdout of the process is not piped");
+        let stderr = child.stderr.take().expect("Bug! stderr of the process is not piped");
+        let stdin = child.stdin.take().expect("Bug! stdin of the process is not piped");
+        let threads = spawn_reader(Pipe::Stdout, stdout, sender.clone())
+            .and_then(|()| spawn_reader(Pipe::Stderr, stderr, sender.clone()))
+            .and_then(|()| spawn_writer(stdin, sender));
+        let stdin = match threads {
+            Ok(stdin) => stdin,
+            Err(e) => {
+                warn!("Failed to spawn ghostscript process pipe thread: {}", e);
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(BuilderError::new(BuilderErrorKind::Process, GsError::Fatal, user_data));
+            },
+        };
+
+        let mut process = GhostscriptProcess {
+            stdin: Some(stdin),
+            writing: false,
+            write_error: None,
+            markers: Markers::new(child.id()),
+            child,
+            events,
+            stdout: PipeOutput::default(),
+            stderr: PipeOutput::default(),
+            callbacks,
+            user_errors,
+            job_control,
+            exited: None,
+            handle: user_data.as_stable_mut() as *mut Q::Target as *mut c_void,
+            user_data: Some(user_data),
+            _encoding: PhantomData,
+        };
+
+        // Init params are processed before "-", so the first job completes after them.
+        match process.interpret_buffer(b"").0.to_result() {
+            Ok(()) => Ok(process),
+            Err(e) => Err(BuilderError::new(BuilderErrorKind::Initialization, e, process.into_inner())),
+        }
+    }
+
+    pub fn user_data_mut(&mut self) -> &mut Q::Target {
+        self.user_data.as_mut().expect("Bug! user_data is missing.").as_stable_mut()
+    }
+}
+
+impl<T, E> Drop for GhostscriptProcess<T, E> {
+    fn drop(&mut self) {
+        if self.exited.is_some() {
+            return;
+        }
+        // End of stdin makes the interpreter exit, as after the last input file.
+        self.stdin = None;
+        let started = Instant::now();
+        while started.elapsed() < EXIT_TIMEOUT {
+            match self.child.try_wait() {
+                Ok(Some(_)) | Err(_) => return,
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        warn!("Ghostscript process {} didn't exit, killing it", self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessStream<'a, T: 'a, E: 'a> {
+    process: &'a mut GhostscriptProcess<T, E>,
+    result: Option<InterpreterResult>,
+    closed: bool,
+    // Timeouts count from the moment the stream is opened.
+    job: ActiveJob,
+}
+
+impl<'a, T: 'a, E: 'a> ProcessStream<'a, T, E> {
+    fn new(process: &'a mut GhostscriptProcess<T, E>) -> Result<Self, InterpreterError> {
+        process.check_exited()?;
+        let job = process.job_control.start();
+        let prologue = process.markers.job_prologue(process.user_errors);
+        let result = {
+            let _guard = job.enter();
+            let sent = process.send(prologue.as_bytes(), &job);
+            process.resume_panic(&job);
+            sent.err().map(|result| job.map_result(result))
+        };
+        if let Some(result) = result {
+            result.into_result()?;
+        }
+        Ok(ProcessStream {
+            process,
+            result: None,
+            closed: false,
+            job,
+        })
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.result.is_some()
+    }
+
+    pub fn close(mut self) -> InterpreterResult {
+        self.close_inner()
+    }
+
+    fn close_inner(&mut self) -> InterpreterResult {
+        self.closed = true;
+        if let Some(result) = self.result {
+            return result;
+        }
+        let epilogue = self.process.markers.job_epilogue();
+        let result = {
+            let _guard = self.job.enter();
+            let result = match self.process.send(epilogue.as_bytes(), &self.job) {
+                Ok(()) => self.process.finish_job(&self.job),
+                Err(result) => result,
+            };
+            self.process.resume_panic(&self.job);
+            self.job.map_result(result)
+        };
+        self.result = Some(result);
+        result
+    }
+}
+
+impl<'a, T: 'a, E: 'a> Write for ProcessStream<'a, T, E> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write_len = ::std::cmp::min(buf.len(), MAX_BYTES_PER_WRITE);
+        if self.result.is_none() {
+            let _guard = self.job.enter();
+            let sent = self.process.send(&buf[..write_len], &self.job);
+            self.process.resume_panic(&self.job);
+            if let Err(result) = sent {
+                self.result = Some(self.job.map_result(result));
+            }
+        }
+        // The job itself only fails at the end of input, it is the process, that can fail earlier.
+        self.result.map_or(Ok(()), InterpreterResult::into_write_result)?;
+        Ok(write_len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, T: 'a, E: 'a> InterpreterStream<'a> for ProcessStream<'a, T, E> {
+    fn is_completed(&self) -> bool {
+        self.is_completed()
+    }
+
+    // The process reports errors only at the end of the job, without the position.
+    fn error_location(&self) -> Option<InputLocation> {
+        None
+    }
+
+    fn close(self) -> InterpreterResult {
+        self.close()
+    }
+}
+
+impl<'a, T: 'a, E: 'a> Drop for ProcessStream<'a, T, E> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let result = self.close_inner();
+            debug!(
+                "Dropped unclosed ProcessStream! Use explicit close() to collect errors. Close result: ({:?})",
+                result
+            );
+        }
+    }
+}
+
+impl<'a, T: 'a, E: StringEncoding + 'a> Interpreter<'a> for GhostscriptProcess<T, E> {
+    type Encoding = E;
+    type Stream = ProcessStream<'a, T, E>;
+
+    fn open_interpreter_stream(&'a mut self) -> Result<Self::Stream, InterpreterError> {
+        ProcessStream::new(self)
+    }
+
+    fn interpret_buffer(&mut self, buffer: &[u8]) -> InterpreterResult {
+        let mut os = match ProcessStream::new(self) {
+            Ok(os) => os,
+            Err(e) => return e.into(),
+        };
+        // Errors will be visible in close() result.
+        let _ = os.write_all(buffer);
+        os.close()
+    }
+
+    fn interpret_reader<R: Read>(&mut self, reader: R, mut opts: ReaderOptions) -> io::Result<ReaderResult> {
+        let mut os = match ProcessStream::new(self) {
+            Ok(os) => os,
+            Err(e) => {
+                return Ok(ReaderResult {
+                    result: e.into(),
+                    bytes_accepted: 0,
+                    cancelled: false,
+                    location: None,
+                })
+            },
+        };
+
+        let (bytes_accepted, cancelled) = match feed_reader(&mut os, reader, &mut opts, MAX_BYTES_PER_WRITE) {
+            Ok(fed) => fed,
+            Err(e) => {
+                let result = os.close();
+                debug!("Reader failed. Close result: ({:?})", result);
+                return Err(e);
+            },
+        };
+
+        Ok(ReaderResult {
+            result: os.close(),
+            bytes_accepted,
+            cancelled,
+            location: None,
+        })
+    }
+
+    fn interpret_file<S>(&mut self, file_name: &S) -> InterpreterResult
+    where
+        S: AsRef<E::RustType> + ?Sized,
+    {
+        let file_name = E::from_rust_to_ffi(file_name);
+        let mut program = postscript_string(file_name.as_ref().to_bytes());
+        program.extend_from_slice(b" run");
+        self.interpret_buffer(&program)
+    }
+}
+
+// Tests, that run the executable, pass without it, when it isn't on PATH.
+#[cfg(test)]
+pub(crate) fn executable_available() -> bool {
+    match Command::new(DEFAULT_EXECUTABLE).arg("--version").stdout(Stdio::null()).status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("Skipped: {} is not available: {}", DEFAULT_EXECUTABLE, e);
+            false
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::GhostscriptBuilder;
+    use callback::panic::PanicCallback;
+    use callback::stdio::StdioCallback;
+
+    #[derive(Debug, Default)]
+    struct Output {
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+    }
+
+    impl PanicCallback for Output {}
+
+    impl StdioCallback for Output {
+        fn write_stdout(&mut self, buf: &[u8]) -> usize {
+            self.stdout.extend_from_slice(buf);
+            buf.len()
+        }
+
+        fn write_stderr(&mut self, buf: &[u8]) -> usize {
+            self.stderr.extend_from_slice(buf);
+            buf.len()
+        }
+    }
+
+    fn start(timeout: Option<Duration>) -> Option<GhostscriptProcess<Box<Output>>> {
+        if !executable_available() {
+            return None;
+        }
+        let mut builder = GhostscriptBuilder::<Output>::new();
+        builder
+            .with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"])
+            .with_stdout(true)
+            .with_stderr(true)
+            .with_timeout(timeout);
+        Some(builder.build_process(DEFAULT_EXECUTABLE, Box::new(Output::default())).expect("Process failed to start"))
+    }
+
+    fn take_stdout(process: &mut GhostscriptProcess<Box<Output>>) -> Vec<u8> {
+        ::std::mem::replace(&mut process.user_data_mut().stdout, Vec::new())
+    }
+
+    #[test]
+    fn jobs_are_framed_and_their_output_is_separated() {
+        let mut process = match start(None) {
+            Some(process) => process,
+            None => return,
+        };
+        assert_eq!(process.interpret_buffer(b"(one) print flush").0, consts::OK);
+        assert_eq!(take_stdout(&mut process), b"one".to_vec());
+
+        // A job written in pieces ends only at the end-of-job marker, and its error doesn't leak into the next one.
+        {
+            let mut os = process.open_interpreter_stream().unwrap();
+            os.write_all(b"(two) pri").unwrap();
+            os.write_all(b"nt flush 1 0 div").unwrap();
+            assert_eq!(os.close().0, consts::UNDEFINED_RESULT);
+        }
+        let output = take_stdout(&mut process);
+        assert!(output.starts_with(b"two"));
+        assert!(!String::from_utf8_lossy(&output).contains("RustGs"));
+
+        assert_eq!(process.interpret_buffer(b"(three) print flush").0, consts::OK);
+        assert_eq!(take_stdout(&mut process), b"three".to_vec());
+        assert!(process.user_data_mut().stderr.is_empty());
+
+        let result = process.interpret_buffer(b"quit");
+        assert_eq!((result.0, result.1), (consts::QUIT, PostscriptExit::Success));
+        assert!(process.has_exited());
+    }
+
+    #[test]
+    fn looping_job_with_unread_input_is_killed_at_timeout() {
+        let mut process = match start(Some(Duration::from_millis(500))) {
+            Some(process) => process,
+            None => return,
+        };
+        // Far more than a pipe holds, so writing it blocks, while the job never reads it.
+        let mut job = b"{} loop\n".to_vec();
+        job.resize(4 << 20, b' ');
+
+        let started = Instant::now();
+        assert_eq!(process.interpret_buffer(&job).0, consts::TIMED_OUT);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(process.has_exited());
+        assert_eq!(process.interpret_buffer(b"(late) print").0, consts::TIMED_OUT);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn crashed_process_fails_every_later_job() {
+        let mut process = match start(None) {
+            Some(process) => process,
+            None => return,
+        };
+        let pid = process.pid().to_string();
+        assert!(Command::new("kill").args(&["-KILL", &pid]).status().unwrap().success());
+
+        let result = process.interpret_buffer(b"(lost) print flush");
+        assert_eq!(result.0, consts::FATAL);
+        assert_eq!(result.1, PostscriptExit::Code(CRASH_EXIT_CODE));
+        assert!(process.has_exited() && process.poll_exited());
+        assert_eq!(process.interpret_buffer(b"1 pop"), result);
+    }
+
+    #[test]
+    fn marker_is_cut_out_of_split_output() {
+        let marker = b"%%Done:";
+        let mut output = PipeOutput::default();
+        output.pending.extend_from_slice(b"page 1\n%%Do");
+        assert_eq!(output.split(marker), (b"page ".to_vec(), None));
+        output.pending.extend_from_slice(b"ne:/undefined\nnext");
+        assert_eq!(output.split(marker), (b"1\n".to_vec(), Some(b"/undefined".to_vec())));
+        assert_eq!(parse_status(b"/undefined"), consts::UNDEFINED);
+        assert_eq!(parse_status(b"/VMerror"), consts::VM_ERROR);
+        assert_eq!(parse_status(b"null"), consts::OK);
+        assert_eq!(output.pending, b"next".to_vec());
+    }
+}
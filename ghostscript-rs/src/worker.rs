@@ -0,0 +1,280 @@
+// An instance, that lives on its own OS thread.
+//
+// Ghostscript<T> is !Send, since it owns a raw interpreter instance, and its callbacks
+// may be called only from the thread, that runs the interpreter. GhostscriptWorker
+// builds the instance on a dedicated thread, and keeps it, along with its user data, there.
+// The worker itself is a cheap, cloneable, Send + Sync handle, that queues jobs
+// to the thread. Jobs run one at a time in submission order, and each one reports back
+// through a JobReceiver. A job, that panics, reports Disconnected, and the worker goes on
+// with the next one. The thread exits, once the last handle is dropped.
+
+use DefaultEncoding;
+use builder::{BuilderErrorKind, BuilderResult};
+use callback::CallbackSafe;
+use callback::stdio::{CaptureStdio, WithStdio};
+use encoding::StringEncoding;
+use error::GsError;
+use instance::Ghostscript;
+use interpreter::{Interpreter, InterpreterError, InterpreterResult};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+pub(crate) type Job<Q, E> = Box<FnOnce(&mut Ghostscript<Q, E>) + Send>;
+
+// The receiver of a panicked job sees Disconnected, since its result sender is dropped while unwinding.
+fn run_jobs<I>(instance: &mut I, jobs: Receiver<Box<FnOnce(&mut I) + Send>>) {
+    for job in jobs {
+        if catch_unwind(AssertUnwindSafe(|| job(instance))).is_err() {
+            warn!("Ghostscript worker job panicked, going on with the next one");
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WorkerError {
+    // The worker thread couldn't be started.
+    Spawn(io::ErrorKind),
+    // The instance failed to build on the worker thread. Includes quitting during initialization.
+    Build(BuilderErrorKind, GsError),
+    // The job panicked, or the worker thread is gone, e.g. because the instance failed to build.
+    Disconnected,
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WorkerError::Spawn(kind) => write!(f, "Failed to spawn ghostscript worker thread: {:?}", kind),
+            WorkerError::Build(kind, error) => write!(f, "Ghostscript worker {}: {}", kind, error),
+            WorkerError::Disconnected => f.write_str("Ghostscript worker thread is gone"),
+        }
+    }
+}
+
+impl Error for WorkerError {
+    fn description(&self) -> &str {
+        match *self {
+            WorkerError::Spawn(_) => "failed to spawn ghostscript worker thread",
+            WorkerError::Build(kind, _) => kind.context(),
+            WorkerError::Disconnected => "ghostscript worker thread is gone",
+        }
+    }
+
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            WorkerError::Build(_, ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Result of a job, that was queued to a worker.
+#[derive(Debug)]
+pub struct JobReceiver<R>(pub(crate) Receiver<R>);
+
+impl<R> JobReceiver<R> {
+    // Blocks until the job is done.
+    pub fn wait(self) -> Result<R, WorkerError> {
+        self.0.recv().map_err(|_| WorkerError::Disconnected)
+    }
+
+    pub fn try_wait(&self) -> Result<Option<R>, WorkerError> {
+        match self.0.try_recv() {
+            Ok(result) => Ok(Some(result)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(WorkerError::Disconnected),
+        }
+    }
+}
+
+pub struct GhostscriptWorker<Q, E = DefaultEncoding> {
+    // Plain Sender isn't Sync.
+    jobs: Arc<Mutex<Sender<Job<Q, E>>>>,
+}
+
+impl<Q, E> Clone for GhostscriptWorker<Q, E> {
+    fn clone(&self) -> Self {
+        GhostscriptWorker {
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+impl<Q, E> fmt::Debug for GhostscriptWorker<Q, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GhostscriptWorker")
+            .field("handles", &Arc::strong_count(&self.jobs))
+            .finish()
+    }
+}
+
+impl<Q: 'static, E: StringEncoding + 'static> GhostscriptWorker<Q, E> {
+    // Runs build on the new thread, so the user data is created there and never leaves it.
+    // Returns after the instance is built.
+    pub fn spawn<F>(build: F) -> Result<Self, WorkerError>
+    where
+        F: FnOnce() -> BuilderResult<Q, E> + Send + 'static,
+    {
+        let (started, startup) = channel();
+        let worker = Self::spawn_notify(build, move |result| {
+            let _ = started.send(result);
+        })?;
+        startup.recv().unwrap_or(Err(WorkerError::Disconnected))?;
+        Ok(worker)
+    }
+
+    // Returns right away. The worker thread reports, whether the instance was built, through started.
+    // Jobs, that are submitted in the meantime, are dropped if it wasn't.
+    pub(crate) fn spawn_notify<F, N>(build: F, started: N) -> Result<Self, WorkerError>
+    where
+        F: FnOnce() -> BuilderResult<Q, E> + Send + 'static,
+        N: FnOnce(Result<(), WorkerError>) + Send + 'static,
+    {
+        let (jobs, job_queue) = channel::<Job<Q, E>>();
+
+        ::std::thread::Builder::new()
+            .name("ghostscript-worker".into())
+            .spawn(move || {
+                let mut instance = match build().running() {
+                    Ok(instance) => instance,
+                    Err(e) => {
+                        let (kind, error) = e.kind_and_error();
+                        started(Err(WorkerError::Build(kind, error)));
+                        return;
+                    },
+                };
+                started(Ok(()));
+
+                run_jobs(&mut instance, job_queue);
+                debug!("All ghostscript worker handles are dropped, shutting down the instance");
+            })
+            .map_err(|e| WorkerError::Spawn(e.kind()))?;
+
+        Ok(GhostscriptWorker {
+            jobs: Arc::new(Mutex::new(jobs)),
+        })
+    }
+
+    pub(crate) fn submit(&self, job: Job<Q, E>) {
+        let jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        if jobs.send(job).is_err() {
+            // The job is dropped along with its result sender, so the receiver sees Disconnected.
+            debug!("Ghostscript worker thread is gone, dropping the job");
+        }
+    }
+
+    // Runs any code against the instance on the worker thread.
+    pub fn run<R, F>(&self, job: F) -> JobReceiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Ghostscript<Q, E>) -> R + Send + 'static,
+    {
+        let (result, receiver) = channel();
+        self.submit(Box::new(move |instance: &mut Ghostscript<Q, E>| {
+            let _ = result.send(job(instance));
+        }));
+        JobReceiver(receiver)
+    }
+
+    pub fn interpret_buffer(&self, buffer: Vec<u8>) -> JobReceiver<InterpreterResult> {
+        self.run(move |instance| instance.interpret_buffer(&buffer))
+    }
+
+    pub fn interpret_file<S>(&self, file_name: S) -> JobReceiver<InterpreterResult>
+    where
+        S: AsRef<E::RustType> + Send + 'static,
+    {
+        self.run(move |instance| instance.interpret_file(&file_name))
+    }
+
+    // Interprets the file, e.g. with the display device, and then extracts
+    // whatever the callbacks collected from the user data.
+    pub fn render<S, R, F>(&self, file_name: S, collect: F) -> JobReceiver<(InterpreterResult, R)>
+    where
+        Q: CallbackSafe,
+        S: AsRef<E::RustType> + Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut Q::Target) -> R + Send + 'static,
+    {
+        self.run(move |instance| {
+            let result = instance.interpret_file(&file_name);
+            (result, collect(instance.user_data_mut()))
+        })
+    }
+}
+
+impl<D, Q, E> GhostscriptWorker<Q, E>
+where
+    Q: CallbackSafe<Target = WithStdio<D, CaptureStdio>> + 'static,
+    E: StringEncoding + 'static,
+{
+    // Runs the program and returns what it printed to stdout.
+    // Requires the builder to enable stdout for the captured user data.
+    pub fn eval(&self, source: Vec<u8>) -> JobReceiver<Result<Vec<u8>, InterpreterError>> {
+        self.run(move |instance| {
+            instance.user_data_mut().stdio.take();
+            let result = instance.interpret_buffer(&source).into_result();
+            let (stdout, _) = instance.user_data_mut().stdio.take();
+            result.map(|_| stdout)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn worker_is_send_and_sync_for_thread_bound_user_data() {
+        assert_send_sync::<GhostscriptWorker<Box<::std::rc::Rc<()>>>>();
+    }
+
+    #[test]
+    fn panicking_job_doesnt_stop_the_next_ones() {
+        let (first, first_done) = channel();
+        let (third, third_done) = channel();
+        let queued: Vec<Box<FnOnce(&mut Vec<u32>) + Send>> = vec![
+            Box::new(move |state: &mut Vec<u32>| {
+                state.push(1);
+                first.send(state.len()).unwrap();
+            }),
+            Box::new(|_: &mut Vec<u32>| panic!("job failed")),
+            Box::new(move |state: &mut Vec<u32>| {
+                state.push(3);
+                third.send(state.clone()).unwrap();
+            }),
+        ];
+        let (jobs, queue) = channel();
+        for job in queued {
+            jobs.send(job).unwrap();
+        }
+        drop(jobs);
+
+        let mut state = Vec::new();
+        run_jobs(&mut state, queue);
+        assert_eq!(JobReceiver(first_done).wait(), Ok(1));
+        assert_eq!(JobReceiver(third_done).wait(), Ok(vec![1, 3]));
+        assert_eq!(state, vec![1, 3]);
+    }
+
+    #[test]
+    #[ignore = "needs libgs, run with --ignored"]
+    fn worker_runs_jobs_after_a_panicked_one() {
+        let build = || {
+            let mut builder = ::builder::GhostscriptBuilder::new();
+            builder.with_init_params(&["-dNODISPLAY", "-dNOPAUSE", "-dQUIET", "-dSAFER"]);
+            builder.build_simple()
+        };
+        let worker = GhostscriptWorker::spawn(build).expect("Worker failed to start");
+
+        let panicked = worker.run::<(), _>(|_| panic!("job failed"));
+        assert_eq!(panicked.wait(), Err(WorkerError::Disconnected));
+        assert_eq!(worker.interpret_buffer(b"1 2 add pop".to_vec()).wait().map(|r| r.0), Ok(::GS_OK));
+        assert_eq!(worker.run(|_| 42).wait(), Ok(42));
+    }
+}
@@ -1,11 +1,244 @@
-fn main() {
-    // The sane default on linux systems.
-    println!("cargo:rustc-link-lib=gs");
+extern crate pkg_config;
+
+#[cfg(feature = "abi-check")]
+#[path = "build/abi.rs"]
+mod abi;
+#[cfg(feature = "generate-bindings")]
+#[path = "build/bindings.rs"]
+mod bindings;
+#[path = "build/vendored.rs"]
+mod vendored;
+
+// The hand-written bindings, that abi module checks.
+#[cfg(feature = "abi-check")]
+#[allow(dead_code)]
+#[path = "src/display.rs"]
+mod display;
+#[cfg(feature = "abi-check")]
+#[allow(dead_code)]
+#[path = "src/error.rs"]
+mod error;
+#[cfg(feature = "abi-check")]
+#[allow(dead_code)]
+#[path = "src/revision.rs"]
+mod revision;
+#[cfg(feature = "abi-check")]
+type GsErrorType = ::std::os::raw::c_int;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+// Environment variables, that override the discovery.
+const LIB_DIR_VAR: &str = "GHOSTSCRIPT_LIB_DIR";
+const INCLUDE_DIR_VAR: &str = "GHOSTSCRIPT_INCLUDE_DIR";
+const STATIC_VAR: &str = "GHOSTSCRIPT_STATIC";
+const LIB_NAME_VAR: &str = "GHOSTSCRIPT_LIB_NAME";
+
+const PKG_CONFIG_MODULE: &str = "ghostscript";
+const DEFAULT_LIB_NAME: &str = "gs";
+
+// Where the linker looks by itself, for systems without ghostscript.pc.
+const SYSTEM_LIB_DIRS: &[&str] = &["/usr/local/lib", "/usr/lib64", "/usr/lib", "/opt/homebrew/lib"];
+
+// Where distributions put iapi.h and gdevdsp.h.
+#[cfg(any(feature = "abi-check", feature = "generate-bindings"))]
+const SYSTEM_INCLUDE_DIRS: &[&str] = &["/usr/include/ghostscript", "/usr/local/include/ghostscript"];
+
+#[derive(Debug, Default)]
+struct Library {
+    version: Option<String>,
+    lib_dirs: Vec<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    statik: bool,
+}
+
+fn env_var(name: &str) -> Option<String> {
+    println!("cargo:rerun-if-env-changed={}", name);
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn env_flag(name: &str) -> bool {
+    match env_var(name) {
+        Some(v) => !(v == "0" || v.eq_ignore_ascii_case("false") || v.eq_ignore_ascii_case("no")),
+        None => false,
+    }
+}
+
+fn lib_file_names(name: &str, statik: bool) -> Vec<String> {
+    if env::var("CARGO_CFG_TARGET_ENV").map(|e| e == "msvc").unwrap_or(false) {
+        return vec![format!("{}.lib", name)];
+    }
+    if statik {
+        vec![format!("lib{}.a", name)]
+    } else {
+        vec![format!("lib{}.so", name), format!("lib{}.dylib", name), format!("lib{}.dll.a", name)]
+    }
+}
+
+fn contains_library(dir: &Path, name: &str, statik: bool) -> bool {
+    lib_file_names(name, statik).iter().any(|f| dir.join(f).is_file())
+}
+
+fn system_lib_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    // Debian-style multiarch directories.
+    if let (Ok(arch), Ok(os), Ok(target_env)) = (
+        env::var("CARGO_CFG_TARGET_ARCH"),
+        env::var("CARGO_CFG_TARGET_OS"),
+        env::var("CARGO_CFG_TARGET_ENV"),
+    ) {
+        dirs.push(PathBuf::from(format!("/usr/lib/{}-{}-{}", arch, os, target_env)));
+    }
+    dirs.extend(SYSTEM_LIB_DIRS.iter().map(PathBuf::from));
+    dirs
+}
 
-    // You can override ghostscript library name/location using
+fn discover(name: &str, statik: bool) -> Result<Library, String> {
+    let include_dirs: Vec<PathBuf> = env_var(INCLUDE_DIR_VAR).map(PathBuf::from).into_iter().collect();
+
+    if let Some(lib_dir) = env_var(LIB_DIR_VAR) {
+        let lib_dir = PathBuf::from(lib_dir);
+        if !contains_library(&lib_dir, name, statik) {
+            return Err(format!(
+                "{}={} doesn't contain any of {:?}",
+                LIB_DIR_VAR,
+                lib_dir.display(),
+                lib_file_names(name, statik)
+            ));
+        }
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-lib={}={}", if statik { "static" } else { "dylib" }, name);
+        return Ok(Library {
+            lib_dirs: vec![lib_dir],
+            include_dirs,
+            statik,
+            ..Library::default()
+        });
+    }
+
+    // pkg-config knows only the library, that it's named after.
+    let pkg_config_error = if name == DEFAULT_LIB_NAME {
+        match pkg_config::Config::new().statik(statik).probe(PKG_CONFIG_MODULE) {
+            Ok(library) => {
+                return Ok(Library {
+                    version: Some(library.version),
+                    lib_dirs: library.link_paths,
+                    include_dirs: if include_dirs.is_empty() { library.include_paths } else { include_dirs },
+                    statik,
+                })
+            },
+            Err(e) => e.to_string(),
+        }
+    } else {
+        format!("skipped for {}={}", LIB_NAME_VAR, name)
+    };
+
+    let system_dirs = system_lib_dirs();
+    if let Some(lib_dir) = system_dirs.iter().find(|dir| contains_library(dir, name, statik)) {
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-lib={}={}", if statik { "static" } else { "dylib" }, name);
+        return Ok(Library {
+            lib_dirs: vec![lib_dir.clone()],
+            include_dirs,
+            statik,
+            ..Library::default()
+        });
+    }
+
+    Err(format!(
+        "pkg-config: {}\nnot in system directories: {:?}",
+        pkg_config_error.trim(),
+        system_dirs
+    ))
+}
+
+fn discover_or_fail() -> Library {
+    // You can still bypass this build script altogether with
     // [target.<your triple>.ghostscript] section in ".cargo/config".
     // See cargo docs for "links" attribute for how.
+    let statik = env_flag(STATIC_VAR);
+    let name = env_var(LIB_NAME_VAR).unwrap_or_else(|| DEFAULT_LIB_NAME.to_string());
+
+    match discover(&name, statik) {
+        Ok(library) => library,
+        Err(e) => panic!(
+            "\n\nGhostscript library ({}) not found.\n{}\n\n\
+             Install ghostscript development files, or point {} (and {}) to the library,\n\
+             optionally with {} and {}=1. Alternatively, enable \"dlopen\" feature \
+             to load the library at runtime.\n\n",
+            name, e, LIB_DIR_VAR, INCLUDE_DIR_VAR, LIB_NAME_VAR, STATIC_VAR
+        ),
+    }
+}
+
+fn out_dir() -> PathBuf {
+    PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set"))
+}
+
+// The headers of the library, that is linked, come first.
+#[cfg(any(feature = "abi-check", feature = "generate-bindings"))]
+fn header_dirs(library_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for dir in library_dirs {
+        dirs.push(dir.clone());
+        dirs.push(dir.join("ghostscript"));
+    }
+    dirs.extend(SYSTEM_INCLUDE_DIRS.iter().map(PathBuf::from));
+    dirs.retain(|dir| dir.is_dir());
+    dirs
+}
+
+// Headers are needed even with "dlopen" feature, GHOSTSCRIPT_INCLUDE_DIR or system ones are used then.
+#[cfg(any(feature = "abi-check", feature = "generate-bindings"))]
+fn check_headers(library: Option<&Library>) {
+    let include_dirs = header_dirs(&match library {
+        Some(library) => library.include_dirs.clone(),
+        None => env_var(INCLUDE_DIR_VAR).map(PathBuf::from).into_iter().collect(),
+    });
+    #[cfg(feature = "abi-check")]
+    {
+        abi::check(&include_dirs, &out_dir()).unwrap_or_else(|e| panic!("\n\nGhostscript ABI check failed.\n{}\n\n", e));
+    }
+    #[cfg(feature = "generate-bindings")]
+    {
+        let with_functions = library.is_some();
+        bindings::generate(&include_dirs, &out_dir(), with_functions)
+            .unwrap_or_else(|e| panic!("\n\nGenerating ghostscript bindings failed.\n{}\n\n", e));
+    }
+}
+
+#[cfg(not(any(feature = "abi-check", feature = "generate-bindings")))]
+fn check_headers(_library: Option<&Library>) {}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=build");
+
+    // With "dlopen" feature the library is loaded at runtime instead.
+    if env::var_os("CARGO_FEATURE_DLOPEN").is_some() {
+        if env::var_os("CARGO_FEATURE_VENDORED").is_some() {
+            panic!("\n\n\"dlopen\" and \"vendored\" features can't be enabled together.\n\n");
+        }
+        check_headers(None);
+        return;
+    }
+
+    let library = if env::var_os("CARGO_FEATURE_VENDORED").is_some() {
+        vendored::build(&out_dir()).unwrap_or_else(|e| panic!("\n\nVendored ghostscript build failed.\n{}\n\n", e))
+    } else {
+        discover_or_fail()
+    };
+    check_headers(Some(&library));
 
-    // Maybe this build script should download precompiled ghostscript
-    // or build it from sources. Suggestions of better ways to do that are welcome.
+    // Visible to build scripts of dependent crates as DEP_GHOSTSCRIPT_*.
+    if let Some(ref version) = library.version {
+        println!("cargo:version={}", version);
+    }
+    if let Some(include_dir) = library.include_dirs.first() {
+        println!("cargo:include={}", include_dir.display());
+    }
+    if let Some(lib_dir) = library.lib_dirs.first() {
+        println!("cargo:lib_dir={}", lib_dir.display());
+    }
+    println!("cargo:static={}", if library.statik { 1 } else { 0 });
 }
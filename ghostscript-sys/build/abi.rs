@@ -0,0 +1,315 @@
+// Checks the hand-written bindings against the installed headers.
+//
+// The constants and structs of display, error and revision modules are compiled into
+// this build script, and a C file with _Static_assert for every value, size and field offset
+// is generated from them and compiled against iapi.h, ierrors.h and gdevdsp.h.
+// Any drift fails the build. Signatures of ffi module are asserted the same way, with _Generic,
+// which accepts the variants of each function, that releases differ in.
+
+use display::*;
+use error;
+use GsErrorType;
+use revision::GsApiRevision;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+extern crate cc;
+
+const HEADERS: &[&str] = &["iapi.h", "ierrors.h", "gdevdsp.h"];
+
+macro_rules! display_consts {
+    ($($name:ident),* $(,)*) => {
+        &[$((stringify!($name), $name as i64)),*]
+    };
+}
+
+const DISPLAY_CONSTS: &[(&str, i64)] = display_consts!(
+    DISPLAY_VERSION_MAJOR_V1,
+    DISPLAY_VERSION_MINOR_V1,
+    DISPLAY_COLORS_MASK,
+    DISPLAY_ALPHA_MASK,
+    DISPLAY_DEPTH_MASK,
+    DISPLAY_ENDIAN_MASK,
+    DISPLAY_FIRSTROW_MASK,
+    DISPLAY_555_MASK,
+    DISPLAY_ROW_ALIGN_MASK,
+    DISPLAY_COLORS_NATIVE,
+    DISPLAY_COLORS_GRAY,
+    DISPLAY_COLORS_RGB,
+    DISPLAY_COLORS_CMYK,
+    DISPLAY_COLORS_SEPARATION,
+    DISPLAY_ALPHA_NONE,
+    DISPLAY_ALPHA_FIRST,
+    DISPLAY_ALPHA_LAST,
+    DISPLAY_UNUSED_FIRST,
+    DISPLAY_UNUSED_LAST,
+    DISPLAY_DEPTH_1,
+    DISPLAY_DEPTH_2,
+    DISPLAY_DEPTH_4,
+    DISPLAY_DEPTH_8,
+    DISPLAY_DEPTH_12,
+    DISPLAY_DEPTH_16,
+    DISPLAY_BIGENDIAN,
+    DISPLAY_LITTLEENDIAN,
+    DISPLAY_TOPFIRST,
+    DISPLAY_BOTTOMFIRST,
+    DISPLAY_NATIVE_555,
+    DISPLAY_NATIVE_565,
+    DISPLAY_ROW_ALIGN_DEFAULT,
+    DISPLAY_ROW_ALIGN_4,
+    DISPLAY_ROW_ALIGN_8,
+    DISPLAY_ROW_ALIGN_16,
+    DISPLAY_ROW_ALIGN_32,
+    DISPLAY_ROW_ALIGN_64,
+);
+
+// Names of the error codes in gserrors.h (ghostscript 9.18 and later).
+const ERROR_CONSTS: &[(&str, GsErrorType)] = &[
+    ("gs_error_ok", error::OK),
+    ("gs_error_unknownerror", error::UNKNOWN_ERROR),
+    ("gs_error_dictfull", error::DICT_FULL),
+    ("gs_error_dictstackoverflow", error::DICT_STACK_OVERFLOW),
+    ("gs_error_dictstackunderflow", error::DICT_STACK_UNDERFLOW),
+    ("gs_error_execstackoverflow", error::EXEC_STACK_OVERFLOW),
+    ("gs_error_interrupt", error::INTERRUPT),
+    ("gs_error_invalidaccess", error::INVALID_ACCESS),
+    ("gs_error_invalidexit", error::INVALID_EXIT),
+    ("gs_error_invalidfileaccess", error::INVALID_FILE_ACCESS),
+    ("gs_error_invalidfont", error::INVALID_FONT),
+    ("gs_error_invalidrestore", error::INVALID_RESTORE),
+    ("gs_error_ioerror", error::IO_ERROR),
+    ("gs_error_limitcheck", error::LIMIT_CHECK),
+    ("gs_error_nocurrentpoint", error::NO_CURRENT_POINT),
+    ("gs_error_rangecheck", error::RANGE_CHECK),
+    ("gs_error_stackoverflow", error::STACK_OVERFLOW),
+    ("gs_error_stackunderflow", error::STACK_UNDERFLOW),
+    ("gs_error_syntaxerror", error::SYNTAX_ERROR),
+    ("gs_error_timeout", error::TIMEOUT),
+    ("gs_error_typecheck", error::TYPECHECK),
+    ("gs_error_undefined", error::UNDEFINED),
+    ("gs_error_undefinedfilename", error::UNDEFINED_FILENAME),
+    ("gs_error_undefinedresult", error::UNDEFINED_RESULT),
+    ("gs_error_unmatchedmark", error::UNMATCHED_MARK),
+    ("gs_error_VMerror", error::VM_ERROR),
+    ("gs_error_configurationerror", error::CONFIGURATION_ERROR),
+    ("gs_error_undefinedresource", error::UNDEFINED_RESOURCE),
+    ("gs_error_unregistered", error::UNREGISTERED),
+    ("gs_error_invalidcontext", error::INVALID_CONTEXT),
+    ("gs_error_invalidid", error::INVALID_ID),
+    ("gs_error_hit_detected", error::HIT_DETECTED),
+    ("gs_error_Fatal", error::FATAL),
+    ("gs_error_Quit", error::QUIT),
+    ("gs_error_InterpreterExit", error::INTERPRETER_EXIT),
+    ("gs_error_Remap_Color", error::REMAP_COLOR),
+    ("gs_error_ExecStackUnderflow", error::EXEC_STACK_UNDERFLOW),
+    ("gs_error_VMreclaim", error::VM_RECLAIM),
+    ("gs_error_NeedInput", error::NEED_INPUT),
+    ("gs_error_Info", error::INFO),
+    ("gs_error_handled", error::HANDLED),
+];
+
+// Mirrors of ffi signatures, with GsRawInstance as void. Each function must have one of its types,
+// so that releases, which only differ in const qualifiers, are listed separately.
+const FUNCTIONS: &[(&str, &[&str])] = &[
+    ("gsapi_revision", &["int (GSDLLAPI *)(gsapi_revision_t *, int)"]),
+    ("gsapi_new_instance", &["int (GSDLLAPI *)(void **, void *)"]),
+    ("gsapi_delete_instance", &["void (GSDLLAPI *)(void *)"]),
+    (
+        "gsapi_set_stdio",
+        &["int (GSDLLAPI *)(void *, \
+           int (GSDLLCALL *)(void *, char *, int), \
+           int (GSDLLCALL *)(void *, const char *, int), \
+           int (GSDLLCALL *)(void *, const char *, int))"],
+    ),
+    ("gsapi_set_poll", &["int (GSDLLAPI *)(void *, int (GSDLLCALL *)(void *))"]),
+    ("gsapi_set_display_callback", &["int (GSDLLAPI *)(void *, display_callback *)"]),
+    // The list became const in later releases.
+    (
+        "gsapi_set_default_device_list",
+        &[
+            "int (GSDLLAPI *)(void *, char *, int)",
+            "int (GSDLLAPI *)(void *, const char *, int)",
+        ],
+    ),
+    ("gsapi_get_default_device_list", &["int (GSDLLAPI *)(void *, char **, int *)"]),
+    ("gsapi_set_arg_encoding", &["int (GSDLLAPI *)(void *, int)"]),
+    ("gsapi_init_with_args", &["int (GSDLLAPI *)(void *, int, char **)"]),
+    ("gsapi_run_string_begin", &["int (GSDLLAPI *)(void *, int, int *)"]),
+    ("gsapi_run_string_continue", &["int (GSDLLAPI *)(void *, const char *, unsigned int, int, int *)"]),
+    ("gsapi_run_string_end", &["int (GSDLLAPI *)(void *, int, int *)"]),
+    ("gsapi_run_string_with_length", &["int (GSDLLAPI *)(void *, const char *, unsigned int, int, int *)"]),
+    ("gsapi_run_string", &["int (GSDLLAPI *)(void *, const char *, int, int *)"]),
+    ("gsapi_run_file", &["int (GSDLLAPI *)(void *, const char *, int, int *)"]),
+    ("gsapi_exit", &["int (GSDLLAPI *)(void *)"]),
+];
+
+struct Layout {
+    rust_name: &'static str,
+    size: usize,
+    offsets: Vec<(&'static str, usize)>,
+}
+
+// Offsets are taken from a zeroed value, which is valid for pointers and Option<fn>.
+macro_rules! layout {
+    ($rust:ident { $($field:ident),* $(,)* }) => {{
+        let value: $rust = unsafe { mem::zeroed() };
+        let base = &value as *const $rust as usize;
+        Layout {
+            rust_name: stringify!($rust),
+            size: mem::size_of::<$rust>(),
+            offsets: vec![$((stringify!($field), &value.$field as *const _ as usize - base)),*],
+        }
+    }};
+}
+
+fn display_callback_v1() -> Layout {
+    layout!(DisplayCallbackV1 {
+        size,
+        version_major,
+        version_minor,
+        display_open,
+        display_preclose,
+        display_close,
+        display_presize,
+        display_size,
+        display_sync,
+        display_page,
+        display_update,
+        display_memalloc,
+        display_memfree,
+    })
+}
+
+fn display_callback_v2() -> Layout {
+    layout!(DisplayCallbackV2 {
+        size,
+        version_major,
+        version_minor,
+        display_open,
+        display_preclose,
+        display_close,
+        display_presize,
+        display_size,
+        display_sync,
+        display_page,
+        display_update,
+        display_memalloc,
+        display_memfree,
+        display_separation,
+    })
+}
+
+fn revision() -> Layout {
+    layout!(GsApiRevision {
+        product,
+        copyright,
+        revision,
+        revisiondate,
+    })
+}
+
+fn assert_equal(source: &mut String, c_expr: &str, expected: i64, what: &str) {
+    writeln!(
+        source,
+        "_Static_assert(({}) == ({}), \"{} differs from ghostscript-sys ({})\");",
+        c_expr, expected, what, expected
+    )
+    .unwrap();
+}
+
+fn assert_offsets(source: &mut String, c_type: &str, layout: &Layout) {
+    for &(field, offset) in &layout.offsets {
+        let what = format!("offset of {}.{}", layout.rust_name, field);
+        assert_equal(source, &format!("offsetof({}, {})", c_type, field), offset as i64, &what);
+    }
+}
+
+fn assert_size(source: &mut String, c_type: &str, layout: &Layout) {
+    let what = format!("size of {}", layout.rust_name);
+    assert_equal(source, &format!("sizeof({})", c_type), layout.size as i64, &what);
+}
+
+// _Generic only selects an association, if the types are compatible, including the qualifiers of parameters.
+fn assert_signature(source: &mut String, function: &str, c_types: &[&str]) {
+    write!(source, "_Static_assert(_Generic(&{}", function).unwrap();
+    for c_type in c_types {
+        write!(source, ", {}: 1", c_type).unwrap();
+    }
+    writeln!(source, ", default: 0), \"signature of {} differs from ghostscript-sys\");", function).unwrap();
+}
+
+fn generate_source(check_layouts: bool) -> String {
+    let mut source = String::new();
+    source.push_str("#include <stddef.h>\n");
+    for header in HEADERS {
+        writeln!(source, "#include \"{}\"", header).unwrap();
+    }
+    source.push('\n');
+
+    for &(name, value) in DISPLAY_CONSTS {
+        assert_equal(&mut source, name, value, name);
+    }
+    source.push_str("#ifdef DISPLAY_VERSION_MAJOR_V2\n");
+    assert_equal(&mut source, "DISPLAY_VERSION_MAJOR_V2", DISPLAY_VERSION_MAJOR_V2 as i64, "DISPLAY_VERSION_MAJOR_V2");
+    assert_equal(&mut source, "DISPLAY_VERSION_MINOR_V2", DISPLAY_VERSION_MINOR_V2 as i64, "DISPLAY_VERSION_MINOR_V2");
+    source.push_str("#endif\n");
+    for &(name, value) in ERROR_CONSTS {
+        assert_equal(&mut source, name, value as i64, name);
+    }
+
+    if check_layouts {
+        let v1 = display_callback_v1();
+        assert_size(&mut source, "struct display_callback_v1_s", &v1);
+        assert_offsets(&mut source, "struct display_callback_v1_s", &v1);
+
+        // Since 9.53 display_callback is V3, that only appends fields to V2.
+        let v2 = display_callback_v2();
+        assert_offsets(&mut source, "display_callback", &v2);
+        source.push_str("#if DISPLAY_VERSION_MAJOR == 2\n");
+        assert_size(&mut source, "display_callback", &v2);
+        source.push_str("#endif\n");
+
+        let revision = revision();
+        assert_size(&mut source, "gsapi_revision_t", &revision);
+        assert_offsets(&mut source, "gsapi_revision_t", &revision);
+    }
+
+    for &(function, c_types) in FUNCTIONS {
+        assert_signature(&mut source, function, c_types);
+    }
+    source
+}
+
+pub fn check(include_dirs: &[PathBuf], out_dir: &Path) -> Result<(), String> {
+    // Layouts of this build script are only those of the target, when it runs there.
+    let check_layouts = env::var("HOST").ok() == env::var("TARGET").ok();
+    if !check_layouts {
+        println!("cargo:warning=Cross-compiling, struct layouts of ghostscript-sys aren't checked against the headers");
+    }
+
+    for header in HEADERS {
+        if let Some(path) = include_dirs.iter().map(|dir| dir.join(header)).find(|path| path.is_file()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+
+    let path = out_dir.join("ghostscript_abi_check.c");
+    fs::write(&path, generate_source(check_layouts)).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+
+    let mut build = cc::Build::new();
+    build.file(&path).includes(include_dirs).cargo_metadata(false);
+    if build.get_compiler().is_like_msvc() {
+        // _Generic and _Static_assert need C11 there.
+        build.flag("/std:c11");
+    }
+    build.try_compile("ghostscript_abi_check").map_err(|e| {
+        format!(
+            "{}\nThe headers in {:?} don't match the bindings of ghostscript-sys, see the errors above. \
+             Point GHOSTSCRIPT_INCLUDE_DIR to the headers of the linked library, if these aren't.",
+            e, include_dirs
+        )
+    })
+}
@@ -0,0 +1,27 @@
+// Bindings generated from the installed headers, for the API, that ffi module doesn't cover yet.
+
+extern crate bindgen;
+
+use std::path::{Path, PathBuf};
+
+const WRAPPER: &str = "#include \"iapi.h\"\n#include \"ierrors.h\"\n#include \"gdevdsp.h\"\n";
+
+pub fn generate(include_dirs: &[PathBuf], out_dir: &Path, with_functions: bool) -> Result<(), String> {
+    let mut builder = bindgen::Builder::default()
+        .header_contents("ghostscript_wrapper.h", WRAPPER)
+        .clang_args(include_dirs.iter().map(|dir| format!("-I{}", dir.display())))
+        .allowlist_type("gsapi_.*|display_.*|gs_error_.*|gs_set_param_type|gs_arg_encoding_.*|GS_ARG_ENCODING_.*")
+        .allowlist_var("DISPLAY_.*|GS_ARG_ENCODING_.*|gs_error_.*")
+        .default_enum_style(bindgen::EnumVariation::Consts)
+        .derive_debug(true)
+        .layout_tests(true)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()));
+    // With "dlopen" feature there is nothing to link the functions against.
+    if with_functions {
+        builder = builder.allowlist_function("gsapi_.*");
+    }
+
+    let bindings = builder.generate().map_err(|e| format!("bindgen failed for headers in {:?}: {}", include_dirs, e))?;
+    let path = out_dir.join("bindings.rs");
+    bindings.write_to_file(&path).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
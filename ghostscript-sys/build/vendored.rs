@@ -0,0 +1,182 @@
+// Static libgs, compiled from a ghostscript source tree, that the user provides. Nothing is downloaded.
+//
+// The tree is copied into OUT_DIR, so that the original stays untouched, and copied again,
+// when GHOSTSCRIPT_SOURCE_DIR points elsewhere or the tree changes. The copy is configured
+// with a minimal set of devices and without optional system dependencies, and "make libgs"
+// builds the static library. Init files and Resource directory are compiled into the library,
+// so the binary doesn't depend on what the system ships.
+
+use super::Library;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+pub const SOURCE_DIR_VAR: &str = "GHOSTSCRIPT_SOURCE_DIR";
+pub const DRIVERS_VAR: &str = "GHOSTSCRIPT_VENDORED_DRIVERS";
+
+const DEFAULT_DRIVERS: &str = "display,bbox,ppmraw,pgmraw,pbmraw,png16m,pnggray,pngalpha,pdfwrite";
+
+const CONFIGURE_ARGS: &[&str] = &[
+    "--enable-compile-inits",
+    "--disable-cups",
+    "--disable-dbus",
+    "--disable-fontconfig",
+    "--disable-gtk",
+    "--without-x",
+    "--without-libidn",
+    "--without-libpaper",
+    "--without-pdftoraster",
+    "--without-tesseract",
+    "--without-ijs",
+];
+
+// Where "make libgs" puts the archive, depending on the revision.
+const ARCHIVE_PATHS: &[&str] = &["bin/gs.a", "bin/libgs.a", "sobin/libgs.a"];
+
+// Libraries, that the static libgs needs from the system.
+const SYSTEM_LIBS: &[&str] = &["m", "pthread", "dl"];
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+// Seconds since the epoch of the newest modification in the tree, so that edits invalidate the copy.
+fn newest_mtime(dir: &Path) -> io::Result<u64> {
+    let mut newest = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let mtime = entry
+            .metadata()?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        newest = ::std::cmp::max(newest, mtime);
+        if entry.file_type()?.is_dir() {
+            newest = ::std::cmp::max(newest, newest_mtime(&entry.path())?);
+        }
+    }
+    Ok(newest)
+}
+
+// GS_VERSION_MAJOR, GS_VERSION_MINOR and GS_VERSION_PATCH from base/version.mak.
+fn read_version(source: &Path) -> Option<String> {
+    let version_mak = fs::read_to_string(source.join("base").join("version.mak")).ok()?;
+    let field = |name: &str| {
+        version_mak.lines().find_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim() == name => Some(value.trim().to_string()),
+                _ => None,
+            }
+        })
+    };
+    Some(format!(
+        "{}.{}.{}",
+        field("GS_VERSION_MAJOR")?,
+        field("GS_VERSION_MINOR")?,
+        field("GS_VERSION_PATCH")?
+    ))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let status = command.status().map_err(|e| format!("failed to run {:?}: {}", command, e))?;
+    if !status.success() {
+        return Err(format!("{:?} failed with {}", command, status));
+    }
+    Ok(())
+}
+
+pub fn build(out_dir: &Path) -> Result<Library, String> {
+    println!("cargo:rerun-if-env-changed={}", SOURCE_DIR_VAR);
+    println!("cargo:rerun-if-env-changed={}", DRIVERS_VAR);
+
+    if env::var("CARGO_CFG_TARGET_FAMILY").map(|f| f != "unix").unwrap_or(true) {
+        return Err("vendored build needs a unix target with sh and make".into());
+    }
+    let source = match env::var_os(SOURCE_DIR_VAR) {
+        Some(source) => PathBuf::from(source),
+        None => {
+            return Err(format!(
+                "{} is not set. Point it to an unpacked ghostscript source release, vendored build doesn't download one.",
+                SOURCE_DIR_VAR
+            ))
+        },
+    };
+    if !source.join("configure").is_file() {
+        return Err(format!("{} doesn't look like a ghostscript source release (no configure script)", source.display()));
+    }
+    let version = read_version(&source);
+    let drivers = env::var(DRIVERS_VAR).unwrap_or_else(|_| DEFAULT_DRIVERS.to_string());
+
+    println!("cargo:rerun-if-changed={}", source.display());
+
+    // Copy afresh, when the tree, its version or anything in it changes. Configure starts over then too.
+    let build_dir = out_dir.join("ghostscript");
+    let newest = newest_mtime(&source).map_err(|e| format!("failed to scan {}: {}", source.display(), e))?;
+    let source_id = format!("{}\n{}\n{}", source.display(), version.as_ref().map_or("unknown", |v| v.as_str()), newest);
+    let source_stamp = out_dir.join(".rust-ghostscript-source");
+    let copied = fs::read_to_string(&source_stamp).ok() == Some(source_id.clone());
+    if !copied || !build_dir.join("configure").is_file() {
+        if build_dir.exists() {
+            fs::remove_dir_all(&build_dir).map_err(|e| format!("failed to remove stale {}: {}", build_dir.display(), e))?;
+        }
+        copy_tree(&source, &build_dir).map_err(|e| format!("failed to copy {} to {}: {}", source.display(), build_dir.display(), e))?;
+        fs::write(&source_stamp, &source_id).map_err(|e| format!("failed to write {}: {}", source_stamp.display(), e))?;
+    }
+
+    // Reconfigure only when the options change.
+    let mut configure_args: Vec<String> = CONFIGURE_ARGS.iter().map(|s| s.to_string()).collect();
+    configure_args.push(format!("--with-drivers={}", drivers));
+    let stamp = build_dir.join(".rust-configure-args");
+    let configured = fs::read_to_string(&stamp).ok() == Some(configure_args.join(" "));
+    if !configured || !build_dir.join("Makefile").is_file() {
+        run(Command::new("sh").arg("./configure").args(&configure_args).current_dir(&build_dir))?;
+        fs::write(&stamp, configure_args.join(" ")).map_err(|e| format!("failed to write {}: {}", stamp.display(), e))?;
+    }
+
+    let jobs = env::var("NUM_JOBS").unwrap_or_else(|_| "1".to_string());
+    run(Command::new("make").arg(format!("-j{}", jobs)).arg("libgs").current_dir(&build_dir))?;
+
+    let archive = ARCHIVE_PATHS
+        .iter()
+        .map(|p| build_dir.join(p))
+        .find(|p| p.is_file())
+        .ok_or_else(|| format!("make libgs didn't produce any of {:?} in {}", ARCHIVE_PATHS, build_dir.display()))?;
+    let lib_dir = out_dir.join("lib");
+    fs::create_dir_all(&lib_dir).map_err(|e| format!("failed to create {}: {}", lib_dir.display(), e))?;
+    fs::copy(&archive, lib_dir.join("libgs.a")).map_err(|e| format!("failed to copy {}: {}", archive.display(), e))?;
+
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib=static=gs");
+    for lib in SYSTEM_LIBS {
+        println!("cargo:rustc-link-lib=dylib={}", lib);
+    }
+    println!("cargo:resource_dir={}", build_dir.join("Resource").display());
+
+    Ok(Library {
+        version,
+        lib_dirs: vec![lib_dir],
+        include_dirs: vec![build_dir.join("psi"), build_dir.join("devices"), build_dir.join("base")],
+        statik: true,
+    })
+}
@@ -0,0 +1,298 @@
+// Runtime loading of the ghostscript library, as an alternative to linking it at build time.
+//
+// Functions in ffi module load the library on the first call, from GHOSTSCRIPT_LIBRARY
+// environment variable or the usual library names, unless load() or load_any() did already.
+// Until a library is loaded, they fail with error::FATAL, which makes instance creation fail
+// instead of the whole program. Symbols, that older revisions lack, fail with error::UNDEFINED.
+// Once loaded, the library stays loaded for the lifetime of the process.
+
+use GsArgEncoding;
+use GsErrorType;
+use GsPExitCode;
+use GsRawInstance;
+use display::DisplayCallback;
+use error;
+use ffi::{PollCallback, StdioInputCallback, StdioOutputCallback};
+use libloading::Library;
+use revision::GsApiRevision;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::sync::Once;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub const LIBRARY_PATH_VAR: &str = "GHOSTSCRIPT_LIBRARY";
+
+#[cfg(target_os = "windows")]
+pub const DEFAULT_LIBRARY_NAMES: &[&str] = &["gsdll64.dll", "gsdll32.dll"];
+#[cfg(target_os = "macos")]
+pub const DEFAULT_LIBRARY_NAMES: &[&str] = &["libgs.dylib", "libgs.10.dylib", "libgs.9.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub const DEFAULT_LIBRARY_NAMES: &[&str] = &["libgs.so", "libgs.so.10", "libgs.so.9"];
+
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    // No candidate could be loaded, with the reason for every one of them.
+    LibraryNotFound(Vec<(OsString, String)>),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::LibraryNotFound(ref attempts) => {
+                f.write_str("Ghostscript library not found")?;
+                for &(ref name, ref reason) in attempts {
+                    write!(f, "; {}: {}", name.to_string_lossy(), reason)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn description(&self) -> &str {
+        "ghostscript library not found"
+    }
+}
+
+trait Fallback {
+    fn fallback(error: GsErrorType) -> Self;
+}
+
+impl Fallback for c_int {
+    fn fallback(error: GsErrorType) -> Self {
+        error
+    }
+}
+
+impl Fallback for () {
+    fn fallback(_error: GsErrorType) -> Self {}
+}
+
+// The function to call, or the error to return instead: FATAL until a library is loaded,
+// UNDEFINED for an optional symbol, that the loaded one lacks.
+fn entry<F>(api: Option<&Api>, symbol: fn(&Api) -> Option<F>) -> Result<F, GsErrorType> {
+    match api {
+        Some(api) => symbol(api).ok_or(error::UNDEFINED),
+        None => Err(error::FATAL),
+    }
+}
+
+macro_rules! dynamic_api {
+    ($( $kind:ident fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty; )*) => {
+        // Entry points of the loaded library. Optional ones are missing from older revisions.
+        struct Api {
+            $( $name: Option<unsafe extern "C" fn($($ty),*) -> $ret>, )*
+            _library: Library,
+        }
+
+        impl Api {
+            unsafe fn new(library: Library) -> Result<Api, String> {
+                Ok(Api {
+                    $( $name: dynamic_api!(@symbol $kind library $name ($($ty),*) -> $ret), )*
+                    _library: library,
+                })
+            }
+
+            // As if every symbol were missing.
+            #[cfg(test)]
+            fn without_symbols(library: Library) -> Api {
+                Api {
+                    $( $name: None, )*
+                    _library: library,
+                }
+            }
+        }
+
+        pub mod functions {
+            use super::*;
+
+            $(
+                pub unsafe fn $name($($arg: $ty),*) -> $ret {
+                    match entry(api(), |api| api.$name) {
+                        Ok(f) => f($($arg),*),
+                        Err(error) => Fallback::fallback(error),
+                    }
+                }
+            )*
+        }
+    };
+    (@symbol required $library:ident $name:ident ($($ty:ty),*) -> $ret:ty) => {
+        Some(*$library
+            .get::<unsafe extern "C" fn($($ty),*) -> $ret>(concat!(stringify!($name), "\0").as_bytes())
+            .map_err(|e| format!("missing {}: {}", stringify!($name), e))?)
+    };
+    (@symbol optional $library:ident $name:ident ($($ty:ty),*) -> $ret:ty) => {
+        $library
+            .get::<unsafe extern "C" fn($($ty),*) -> $ret>(concat!(stringify!($name), "\0").as_bytes())
+            .ok()
+            .map(|symbol| *symbol)
+    };
+}
+
+dynamic_api! {
+    required fn gsapi_revision(pr: *mut GsApiRevision, len: c_int) -> c_int;
+    required fn gsapi_new_instance(pinstance: *mut *mut GsRawInstance, caller_handle: *mut c_void) -> GsErrorType;
+    required fn gsapi_delete_instance(instance: *mut GsRawInstance) -> ();
+    required fn gsapi_set_stdio(
+        instance: *mut GsRawInstance,
+        stdin_fn: Option<StdioInputCallback>,
+        stdout_fn: Option<StdioOutputCallback>,
+        stderr_fn: Option<StdioOutputCallback>
+    ) -> GsErrorType;
+    required fn gsapi_set_poll(instance: *mut c_void, poll_fn: Option<PollCallback>) -> GsErrorType;
+    required fn gsapi_set_display_callback(instance: *mut GsRawInstance, callback: *mut DisplayCallback) -> GsErrorType;
+    optional fn gsapi_set_default_device_list(instance: *mut GsRawInstance, list: *mut c_char, listlen: c_int) -> GsErrorType;
+    optional fn gsapi_get_default_device_list(
+        instance: *mut GsRawInstance,
+        list: *mut *mut c_char,
+        listlen: *mut c_int
+    ) -> GsErrorType;
+    optional fn gsapi_set_arg_encoding(instance: *mut GsRawInstance, encoding: GsArgEncoding) -> GsErrorType;
+    required fn gsapi_init_with_args(instance: *mut GsRawInstance, argc: c_int, argv: *mut *mut c_char) -> GsErrorType;
+    required fn gsapi_run_string_begin(instance: *mut GsRawInstance, user_errors: c_int, pexit_code: *mut GsPExitCode) -> GsErrorType;
+    required fn gsapi_run_string_continue(
+        instance: *mut GsRawInstance,
+        str: *const c_char,
+        length: c_uint,
+        user_errors: c_int,
+        pexit_code: *mut GsPExitCode
+    ) -> GsErrorType;
+    required fn gsapi_run_string_end(instance: *mut GsRawInstance, user_errors: c_int, pexit_code: *mut GsPExitCode) -> GsErrorType;
+    required fn gsapi_run_string_with_length(
+        instance: *mut GsRawInstance,
+        str: *const c_char,
+        length: c_uint,
+        user_errors: c_int,
+        pexit_code: *mut GsPExitCode
+    ) -> GsErrorType;
+    required fn gsapi_run_string(
+        instance: *mut GsRawInstance,
+        str: *const c_char,
+        user_errors: c_int,
+        pexit_code: *mut GsPExitCode
+    ) -> GsErrorType;
+    required fn gsapi_run_file(
+        instance: *mut GsRawInstance,
+        file_name: *const c_char,
+        user_errors: c_int,
+        pexit_code: *mut GsPExitCode
+    ) -> GsErrorType;
+    required fn gsapi_exit(instance: *mut GsRawInstance) -> GsErrorType;
+}
+
+static API: AtomicPtr<Api> = AtomicPtr::new(0 as *mut Api);
+static DEFAULT_LOAD: Once = Once::new();
+
+fn api() -> Option<&'static Api> {
+    if API.load(Ordering::SeqCst).is_null() {
+        DEFAULT_LOAD.call_once(|| {
+            let _ = load_default();
+        });
+    }
+    unsafe { API.load(Ordering::SeqCst).as_ref() }
+}
+
+pub fn is_loaded() -> bool {
+    !API.load(Ordering::SeqCst).is_null()
+}
+
+pub fn load<P: AsRef<OsStr>>(path: P) -> Result<(), LoadError> {
+    load_any(&[path])
+}
+
+// Takes the first candidate, that loads and has all the required symbols.
+// Does nothing, if a library is loaded already.
+pub fn load_any<P: AsRef<OsStr>>(candidates: &[P]) -> Result<(), LoadError> {
+    let mut attempts = Vec::new();
+    for candidate in candidates {
+        if is_loaded() {
+            return Ok(());
+        }
+        let candidate = candidate.as_ref();
+        let loaded = unsafe { Library::new(candidate).map_err(|e| e.to_string()).and_then(|l| Api::new(l)) };
+        match loaded {
+            Ok(api) => {
+                let api = Box::into_raw(Box::new(api));
+                if API.compare_exchange(0 as *mut Api, api, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+                    // Another thread has loaded one meanwhile.
+                    drop(unsafe { Box::from_raw(api) });
+                }
+                return Ok(());
+            },
+            Err(reason) => attempts.push((candidate.to_owned(), reason)),
+        }
+    }
+    if is_loaded() {
+        return Ok(());
+    }
+    Err(LoadError::LibraryNotFound(attempts))
+}
+
+// Path from GHOSTSCRIPT_LIBRARY environment variable, if set, otherwise the usual library names.
+pub fn load_default() -> Result<(), LoadError> {
+    match ::std::env::var_os(LIBRARY_PATH_VAR) {
+        Some(path) => load(path),
+        None => load_any(DEFAULT_LIBRARY_NAMES),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing(name: &str) -> OsString {
+        ::std::env::temp_dir().join("ghostscript-sys-test").join(name).into_os_string()
+    }
+
+    #[test]
+    fn every_candidate_is_tried_in_order() {
+        let candidates = [missing("libgs-first.so"), missing("libgs-second.so")];
+        match load_any(&candidates) {
+            Err(LoadError::LibraryNotFound(ref attempts)) => {
+                let tried: Vec<_> = attempts.iter().map(|&(ref name, _)| name.clone()).collect();
+                assert_eq!(tried, candidates.to_vec());
+            },
+            // Some other test, or the environment, has loaded a real library already.
+            Ok(()) => assert!(is_loaded()),
+        }
+    }
+
+    #[test]
+    fn nonexistent_path_is_not_found() {
+        match load(missing("libgs-nonexistent.so")) {
+            Err(LoadError::LibraryNotFound(ref attempts)) => assert_eq!(attempts.len(), 1),
+            Ok(()) => assert!(is_loaded()),
+        }
+    }
+
+    #[test]
+    fn load_error_lists_every_attempt() {
+        let error = LoadError::LibraryNotFound(vec![
+            ("libgs.so".into(), "not found".to_owned()),
+            ("libgs.so.10".into(), "missing gsapi_revision".to_owned()),
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Ghostscript library not found; libgs.so: not found; libgs.so.10: missing gsapi_revision"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_symbols_fail_instead_of_crashing() {
+        let this = || Library::from(::libloading::os::unix::Library::this());
+
+        // The test executable has none of the required symbols.
+        match unsafe { Api::new(this()) } {
+            Err(reason) => assert!(reason.starts_with("missing gsapi_revision"), "{}", reason),
+            Ok(_) => panic!("Test executable passed for a ghostscript library"),
+        }
+
+        let api = Api::without_symbols(this());
+        assert_eq!(entry(Some(&api), |api| api.gsapi_set_arg_encoding).err(), Some(error::UNDEFINED));
+        assert_eq!(entry(None, |api| api.gsapi_set_arg_encoding).err(), Some(error::FATAL));
+    }
+}
@@ -1,14 +1,24 @@
+#[cfg(not(feature = "dlopen"))]
 use GsArgEncoding;
 use GsErrorType;
+#[cfg(not(feature = "dlopen"))]
 use GsPExitCode;
+#[cfg(not(feature = "dlopen"))]
 use GsRawInstance;
 
-use std::os::raw::{c_char, c_int, c_uint, c_void};
+#[cfg(not(feature = "dlopen"))]
+use std::os::raw::c_uint;
+use std::os::raw::{c_char, c_int, c_void};
 
 pub type StdioInputCallback = unsafe extern "C" fn(caller_handle: *mut c_void, buf: *mut c_char, len: c_int) -> c_int;
 pub type StdioOutputCallback = unsafe extern "C" fn(caller_handle: *mut c_void, str: *const c_char, len: c_int) -> c_int;
 pub type PollCallback = unsafe extern "C" fn(caller_handle: *mut c_void) -> GsErrorType;
 
+// With "dlopen" feature, the same functions call into the library, that dynamic module loads.
+#[cfg(feature = "dlopen")]
+pub use dynamic::functions::*;
+
+#[cfg(not(feature = "dlopen"))]
 extern "C" {
     pub fn gsapi_revision(pr: *mut ::revision::GsApiRevision, len: c_int) -> c_int;
 
@@ -1,10 +1,22 @@
 #![deny(improper_ctypes)]
 
+#[cfg(feature = "dlopen")]
+extern crate libloading;
+
+#[cfg(feature = "dlopen")]
+pub mod dynamic;
 pub mod ffi;
 pub mod error;
 pub mod display;
 pub mod revision;
 
+// With "dlopen" feature it has only types and constants.
+#[cfg(feature = "generate-bindings")]
+#[allow(non_camel_case_types, non_upper_case_globals, non_snake_case, dead_code)]
+pub mod generated {
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
 use std::os::raw::{c_int, c_uint};
 
 #[derive(Debug)]